@@ -0,0 +1,139 @@
+use std::num::NonZeroU64;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{bail, ContextCompat};
+use lyrebird_protocol::client::Client;
+use lyrebird_protocol::{format_duration, ClientMessage, Queueable};
+
+/// Control lyrebird from the command line
+#[derive(Parser)]
+struct Args {
+    /// Address of the bot's WebSocket server
+    #[arg(long, env = "LYRECTL_URL", default_value = "ws://127.0.0.1:9100")]
+    url: String,
+    /// Token issued with `/token issue`
+    #[arg(long, env = "LYRECTL_TOKEN", hide_env_values = true)]
+    token: String,
+    /// ID of the server to control
+    #[arg(long, env = "LYRECTL_GUILD")]
+    guild: NonZeroU64,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List queue contents
+    Queue,
+    /// Add a song to queue from the given URL
+    Play {
+        url: String,
+        /// Treat the argument as a search term and play the first result
+        #[arg(short, long)]
+        search: bool,
+    },
+    /// Skip the current song
+    Skip,
+    /// Pause the current track
+    Pause,
+    /// Resume the current track
+    Resume,
+    /// Seek to a position in the current track, e.g. `90`, `1:30` or `1:02:03`
+    Seek {
+        #[arg(value_parser = parse_position)]
+        position: Duration,
+    },
+    /// Stop the current track and clear the queue
+    Clear,
+    /// Shuffle queued tracks
+    Shuffle,
+    /// Toggle loop mode for the current track
+    Loop,
+    /// Reorder a track in the queue
+    Move { from: usize, to: usize },
+    /// Swap two tracks in the queue
+    Swap { a: usize, b: usize },
+    /// Remove a track from the queue by its index
+    Remove { index: usize },
+    /// Join a voice channel
+    Join { channel: NonZeroU64 },
+    /// Leave the voice channel
+    Leave,
+    /// Print playback events as they happen
+    Watch,
+}
+
+fn parse_position(s: &str) -> Result<Duration, String> {
+    let mut secs = 0;
+    for part in s.split(':') {
+        let n: u64 = part.parse().map_err(|_| format!("invalid position: {s}"))?;
+        secs = secs * 60 + n;
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    let mut client = Client::connect(&args.url, &args.token, args.guild).await?;
+
+    let message = match args.command {
+        Command::Queue => {
+            print_queue(&mut client).await?;
+            return Ok(());
+        }
+        Command::Watch => {
+            client.request(ClientMessage::Subscribe).await?;
+            loop {
+                println!("{}", client.next_event().await?);
+            }
+        }
+        Command::Play { url, search } => ClientMessage::Enqueue(Queueable::Ytdl {
+            arg: if search {
+                format!("ytsearch1:{url}")
+            } else if url.starts_with("http") {
+                url
+            } else {
+                bail!("Argument must be a valid URL");
+            },
+        }),
+        Command::Skip => ClientMessage::Skip,
+        Command::Pause => ClientMessage::Pause,
+        Command::Resume => ClientMessage::Resume,
+        Command::Seek { position } => ClientMessage::Seek(position),
+        Command::Clear => ClientMessage::ClearAll,
+        Command::Shuffle => ClientMessage::Shuffle,
+        Command::Loop => ClientMessage::ToggleLoop,
+        Command::Move { from, to } => ClientMessage::Move { from, to },
+        Command::Swap { a, b } => ClientMessage::Swap { a, b },
+        Command::Remove { index } => ClientMessage::Remove(index),
+        Command::Join { channel } => ClientMessage::Join { channel },
+        Command::Leave => ClientMessage::Leave,
+    };
+    client.request(message).await?;
+    Ok(())
+}
+
+async fn print_queue(client: &mut Client) -> color_eyre::Result<()> {
+    let snapshot = client.snapshot().await?;
+    let current = snapshot.tracks.first().context("queue is empty")?;
+    let duration = current.track.length();
+    let position = snapshot.position.unwrap_or_default();
+    let status = if snapshot.paused { " (paused)" } else { "" };
+    let looping = if current.is_loop_enabled {
+        " (looping)"
+    } else {
+        ""
+    };
+    println!(
+        "Now Playing: {} - {} / {duration}{status}{looping}",
+        current.track,
+        format_duration(position)
+    );
+    for (n, entry) in snapshot.tracks.iter().enumerate().skip(1) {
+        println!("{n}: {}", entry.track);
+    }
+    Ok(())
+}
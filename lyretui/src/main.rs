@@ -0,0 +1,307 @@
+use std::num::NonZeroU64;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind};
+use futures_util::StreamExt;
+use lyrebird_protocol::client::{Client, Error};
+use lyrebird_protocol::{format_duration, ClientMessage, QueueSnapshot, Queueable, SearchResult};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Clear, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::time::interval;
+
+const HELP: &str =
+    "q quit · space pause · n skip · J/K move · d remove · s shuffle · l loop · / search";
+
+/// Manage lyrebird's queue from the terminal
+#[derive(Parser)]
+struct Args {
+    /// Address of the bot's WebSocket server
+    #[arg(long, env = "LYRECTL_URL", default_value = "ws://127.0.0.1:9100")]
+    url: String,
+    /// Token issued with `/token issue`
+    #[arg(long, env = "LYRECTL_TOKEN", hide_env_values = true)]
+    token: String,
+    /// ID of the server to control
+    #[arg(long, env = "LYRECTL_GUILD")]
+    guild: NonZeroU64,
+}
+
+enum Mode {
+    Queue,
+    SearchInput(String),
+    SearchResults(Vec<SearchResult>, ListState),
+}
+
+struct App {
+    snapshot: QueueSnapshot,
+    /// When `snapshot` was taken, to advance the progress bar between refreshes.
+    fetched_at: Instant,
+    queue: ListState,
+    mode: Mode,
+    status: String,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    let mut client = Client::connect(&args.url, &args.token, args.guild).await?;
+    client.request(ClientMessage::Subscribe).await?;
+    let mut app = App::new();
+    app.refresh(&mut client).await?;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal, &mut client).await;
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new() -> Self {
+        App {
+            snapshot: QueueSnapshot::default(),
+            fetched_at: Instant::now(),
+            queue: ListState::default().with_selected(Some(0)),
+            mode: Mode::Queue,
+            status: HELP.into(),
+        }
+    }
+
+    async fn run(
+        mut self,
+        terminal: &mut DefaultTerminal,
+        client: &mut Client,
+    ) -> color_eyre::Result<()> {
+        let mut keys = EventStream::new();
+        let mut tick = interval(Duration::from_secs(1));
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            tokio::select! {
+                event = keys.next() => match event {
+                    Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                        if !self.on_key(client, key).await? {
+                            return Ok(());
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                },
+                event = client.next_event() => {
+                    self.status = event?.to_string();
+                    self.refresh(client).await?;
+                }
+                _ = tick.tick() => self.refresh(client).await?,
+            }
+        }
+    }
+
+    /// Fetches the queue again. While the bot can't show one, e.g. because it isn't in
+    /// a voice channel, the queue is shown empty with the reason in the status line.
+    async fn refresh(&mut self, client: &mut Client) -> Result<(), Error> {
+        self.snapshot = match client.snapshot().await {
+            Ok(snapshot) => snapshot,
+            Err(Error::Server(e)) => {
+                self.status = e;
+                QueueSnapshot::default()
+            }
+            Err(e) => return Err(e),
+        };
+        self.fetched_at = Instant::now();
+        let last = self.snapshot.tracks.len().saturating_sub(1);
+        if self.queue.selected().is_some_and(|i| i > last) {
+            self.queue.select(Some(last));
+        }
+        Ok(())
+    }
+
+    /// Sends `message`, showing errors from the bot in the status line.
+    async fn send(&mut self, client: &mut Client, message: ClientMessage) -> Result<(), Error> {
+        match client.request(message).await {
+            Ok(_) => {}
+            Err(Error::Server(e)) => self.status = e,
+            Err(e) => return Err(e),
+        }
+        self.refresh(client).await
+    }
+
+    /// Returns `false` when the user wants to quit.
+    async fn on_key(&mut self, client: &mut Client, key: KeyEvent) -> color_eyre::Result<bool> {
+        match &mut self.mode {
+            Mode::Queue => return self.on_queue_key(client, key).await,
+            Mode::SearchInput(keyword) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Queue,
+                KeyCode::Backspace => {
+                    keyword.pop();
+                }
+                KeyCode::Char(c) => keyword.push(c),
+                KeyCode::Enter => {
+                    let keyword = std::mem::take(keyword);
+                    self.status = format!("Searching for \"{keyword}\"…");
+                    match client.search(keyword, 10).await {
+                        Ok(results) => {
+                            self.status = "enter enqueue · esc close".into();
+                            self.mode = Mode::SearchResults(
+                                results,
+                                ListState::default().with_selected(Some(0)),
+                            );
+                        }
+                        Err(Error::Server(e)) => {
+                            self.status = e;
+                            self.mode = Mode::Queue;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                _ => {}
+            },
+            Mode::SearchResults(results, state) => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    self.mode = Mode::Queue;
+                    self.status = HELP.into();
+                }
+                KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+                KeyCode::Enter => {
+                    if let Some(result) = state.selected().and_then(|i| results.get(i)) {
+                        let status = format!("Queued: {}", result.title_or_url());
+                        let message = ClientMessage::Enqueue(Queueable::Ytdl {
+                            arg: result.url.clone(),
+                        });
+                        self.status = status;
+                        self.send(client, message).await?;
+                    }
+                }
+                _ => {}
+            },
+        }
+        Ok(true)
+    }
+
+    async fn on_queue_key(
+        &mut self,
+        client: &mut Client,
+        key: KeyEvent,
+    ) -> color_eyre::Result<bool> {
+        let selected = self.queue.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Up | KeyCode::Char('k') => self.queue.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') if selected + 1 < self.snapshot.tracks.len() => {
+                self.queue.select_next()
+            }
+            KeyCode::Char(' ') => {
+                let message = if self.snapshot.paused {
+                    ClientMessage::Resume
+                } else {
+                    ClientMessage::Pause
+                };
+                self.send(client, message).await?;
+            }
+            KeyCode::Char('n') => self.send(client, ClientMessage::Skip).await?,
+            KeyCode::Char('K') if selected > 1 => {
+                let to = selected - 1;
+                self.send(client, ClientMessage::Move { from: selected, to })
+                    .await?;
+                self.queue.select(Some(to));
+            }
+            KeyCode::Char('J') if selected + 1 < self.snapshot.tracks.len() => {
+                let to = selected + 1;
+                self.send(client, ClientMessage::Move { from: selected, to })
+                    .await?;
+                self.queue.select(Some(to));
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                self.send(client, ClientMessage::Remove(selected)).await?
+            }
+            KeyCode::Char('s') => self.send(client, ClientMessage::Shuffle).await?,
+            KeyCode::Char('l') => self.send(client, ClientMessage::ToggleLoop).await?,
+            KeyCode::Char('/') => self.mode = Mode::SearchInput(String::new()),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [now_playing, queue, status] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_now_playing(frame, now_playing);
+
+        let items = self.snapshot.tracks.iter().enumerate().map(|(n, entry)| {
+            let duration = entry.track.length();
+            let n = if n == 0 { "▶".into() } else { n.to_string() };
+            ListItem::new(format!("{n}: {} ({duration})", entry.track))
+        });
+        let list = List::new(items)
+            .block(Block::bordered().title("Queue"))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, queue, &mut self.queue);
+
+        if let Mode::SearchResults(results, state) = &mut self.mode {
+            let items = results.iter().map(|result| {
+                ListItem::new(format!(
+                    "{} - {}",
+                    result.artist.as_deref().unwrap_or("unknown artist"),
+                    result.title_or_url()
+                ))
+            });
+            let list = List::new(items)
+                .block(Block::bordered().title("Search results"))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+            frame.render_widget(Clear, queue);
+            frame.render_stateful_widget(list, queue, state);
+        }
+
+        let status_line = match &self.mode {
+            Mode::SearchInput(keyword) => format!("Search: {keyword}█"),
+            _ => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    fn draw_now_playing(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Now Playing");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [title, progress] =
+            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(inner);
+
+        let Some(current) = self.snapshot.tracks.first() else {
+            frame.render_widget(Paragraph::new("queue is empty"), title);
+            return;
+        };
+        let mut line = current.track.to_string();
+        if self.snapshot.paused {
+            line.push_str(" (paused)");
+        }
+        if current.is_loop_enabled {
+            line.push_str(" (looping)");
+        }
+        frame.render_widget(Paragraph::new(Line::from(line)), title);
+
+        let mut position = self.snapshot.position.unwrap_or_default();
+        if !self.snapshot.paused {
+            position += self.fetched_at.elapsed();
+        }
+        let (ratio, total) = match current.track.duration {
+            Some(duration) if !duration.is_zero() => (
+                (position.as_secs_f64() / duration.as_secs_f64()).min(1.0),
+                format_duration(duration),
+            ),
+            _ => (0.0, current.track.length()),
+        };
+        let gauge = Gauge::default()
+            .ratio(ratio)
+            .label(format!("{} / {total}", format_duration(position)));
+        frame.render_widget(gauge, progress);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::num::NonZeroU64;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::{ClientMessage, QueueSnapshot, Request, Response, SearchResult, ServerMessage};
+
+#[derive(Debug)]
+pub enum Error {
+    WebSocket(tungstenite::Error),
+    Json(serde_json::Error),
+    /// The server answered with [`Response::Error`].
+    Server(String),
+    Closed,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WebSocket(e) => write!(f, "websocket error: {e}"),
+            Error::Json(e) => write!(f, "invalid message: {e}"),
+            Error::Server(e) => f.write_str(e),
+            Error::Closed => f.write_str("connection closed by the server"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::WebSocket(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// A connection to the bot's WebSocket server, controlling a single guild.
+pub struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    guild: NonZeroU64,
+    /// Events that arrived while waiting for a response.
+    pending: VecDeque<ServerMessage>,
+}
+
+impl Client {
+    pub async fn connect(url: &str, token: &str, guild: NonZeroU64) -> Result<Self, Error> {
+        let mut request = url.into_client_request()?;
+        let auth = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|e| Error::WebSocket(tungstenite::Error::HttpFormat(e.into())))?;
+        request.headers_mut().insert("authorization", auth);
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(Client {
+            ws,
+            guild,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Sends `message` and waits for the server to answer it.
+    pub async fn request(&mut self, message: ClientMessage) -> Result<Response, Error> {
+        let request = Request {
+            guild: self.guild,
+            message,
+        };
+        self.ws
+            .send(Message::text(serde_json::to_string(&request)?))
+            .await?;
+        loop {
+            match self.receive().await? {
+                Response::Event { message, .. } => self.pending.push_back(message),
+                Response::Error(e) => return Err(Error::Server(e)),
+                response => return Ok(response),
+            }
+        }
+    }
+
+    pub async fn snapshot(&mut self) -> Result<QueueSnapshot, Error> {
+        match self.request(ClientMessage::QueueSnapshot).await? {
+            Response::Queue(snapshot) => Ok(snapshot),
+            _ => Err(Error::Server("expected a queue snapshot".into())),
+        }
+    }
+
+    pub async fn search(
+        &mut self,
+        keyword: String,
+        num: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        match self.request(ClientMessage::Search { keyword, num }).await? {
+            Response::SearchResults(results) => Ok(results),
+            _ => Err(Error::Server("expected search results".into())),
+        }
+    }
+
+    /// Waits for the next event in the guild. Requires [`ClientMessage::Subscribe`] first.
+    pub async fn next_event(&mut self) -> Result<ServerMessage, Error> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message);
+        }
+        loop {
+            if let Response::Event { message, .. } = self.receive().await? {
+                return Ok(message);
+            }
+        }
+    }
+
+    async fn receive(&mut self) -> Result<Response, Error> {
+        loop {
+            match self.ws.next().await.ok_or(Error::Closed)?? {
+                Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                Message::Close(_) => return Err(Error::Closed),
+                _ => continue,
+            }
+        }
+    }
+}
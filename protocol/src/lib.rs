@@ -0,0 +1,227 @@
+//! Types shared by lyrebird and the clients that control it remotely.
+//!
+//! Clients send a [`Request`] as a JSON text message and get exactly one [`Response`]
+//! back for each, in order. [`Response::Event`]s are pushed in between for guilds the
+//! client subscribed to.
+
+use std::fmt;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "client")]
+pub mod client;
+
+/// Something that can be added to the queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Queueable {
+    /// Anything `yt-dlp` can play, including `ytsearch1:` style search terms.
+    Ytdl { arg: String },
+    /// An audio file in one of the bot's library directories. Relative paths
+    /// are looked up in each directory.
+    File { path: PathBuf },
+    /// An audio file streamed straight from a web server, without yt-dlp.
+    Http { url: String },
+    /// An internet radio station, given as a stream URL or an M3U/PLS playlist.
+    Radio { url: String },
+    /// A file uploaded to Discord.
+    Attachment { url: String, filename: String },
+    /// A podcast episode's audio, which resumes where the guild last stopped it.
+    Podcast {
+        url: String,
+        show: String,
+        title: String,
+    },
+}
+
+/// A message that is sent to the server to control musicbot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Stops the current song and clears the queue.
+    ClearAll,
+    /// Pause current playback
+    Pause,
+    /// Resume current playback
+    Resume,
+    /// Skip the current song.
+    Skip,
+    /// Seek to a position in the current song.
+    Seek(Duration),
+    /// Add a track to the end of the queue.
+    Enqueue(Queueable),
+    /// Reorder a track in the queue.
+    Move { from: usize, to: usize },
+    /// Swap two tracks in the queue.
+    Swap { a: usize, b: usize },
+    /// Remove a track from the queue by its index.
+    Remove(usize),
+    /// Shuffle queued tracks.
+    Shuffle,
+    /// Toggle loop mode for the current track.
+    ToggleLoop,
+    /// Join a voice channel in the guild.
+    Join { channel: NonZeroU64 },
+    /// Leave the voice channel.
+    Leave,
+    /// Ask for the current queue, answered with [`Response::Queue`].
+    QueueSnapshot,
+    /// Search YouTube like `/search` does, answered with [`Response::SearchResults`].
+    Search { keyword: String, num: usize },
+    /// Start receiving [`ServerMessage`]s for the guild.
+    Subscribe,
+    /// Stop receiving [`ServerMessage`]s for the guild.
+    Unsubscribe,
+}
+
+/// A change in playback state, pushed to clients subscribed to a guild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// A track started playing from the beginning, or a radio station started
+    /// a new song.
+    TrackStarted(TrackInfo),
+    /// A track finished, was skipped, or was stopped.
+    TrackEnded(TrackInfo),
+    /// A track could not be played.
+    TrackErrored { track: TrackInfo, error: String },
+    /// Tracks were added, removed or reordered.
+    QueueChanged,
+    /// The current track was paused.
+    Paused,
+    /// The current track was resumed.
+    Resumed,
+    /// The current track was moved to a new position.
+    Seeked(Duration),
+    /// The bot joined a voice channel.
+    Joined { channel: NonZeroU64 },
+    /// The bot left the voice channel, or was kicked or disconnected for good.
+    Left,
+}
+
+/// A one-line description of the event for showing to users.
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::TrackStarted(track) => write!(f, "Now playing: {track}"),
+            ServerMessage::TrackEnded(track) => write!(f, "Finished: {track}"),
+            ServerMessage::TrackErrored { track, error } => {
+                write!(f, "Failed to play {track}: {error}")
+            }
+            ServerMessage::QueueChanged => f.write_str("Queue changed"),
+            ServerMessage::Paused => f.write_str("Paused"),
+            ServerMessage::Resumed => f.write_str("Resumed"),
+            ServerMessage::Seeked(position) => {
+                write!(f, "Seeked to {}", format_duration(*position))
+            }
+            ServerMessage::Joined { channel } => write!(f, "Joined channel {channel}"),
+            ServerMessage::Left => f.write_str("Left voice channel"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<Duration>,
+    /// URL of an image for the track, such as a video's thumbnail.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    /// The track is a stream without an end, like a radio station.
+    #[serde(default)]
+    pub live: bool,
+}
+
+impl TrackInfo {
+    /// The track's duration for display, or `live` for endless streams.
+    pub fn length(&self) -> String {
+        match self.duration {
+            Some(duration) => format_duration(duration),
+            None if self.live => "live".into(),
+            None => "unknown".into(),
+        }
+    }
+}
+
+impl fmt::Display for TrackInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}",
+            self.artist.as_deref().unwrap_or("unknown artist"),
+            self.title.as_deref().unwrap_or("unknown title")
+        )
+    }
+}
+
+/// The state of a guild's queue, the same information `/queue` shows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    /// Playback position in the current track, if anything is playing.
+    pub position: Option<Duration>,
+    pub paused: bool,
+    /// The current track followed by the rest of the queue.
+    pub tracks: Vec<QueueEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub id: u32,
+    #[serde(flatten)]
+    pub track: TrackInfo,
+    pub queueable: Queueable,
+    pub is_loop_enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub duration: Option<Duration>,
+}
+
+impl SearchResult {
+    pub fn title_or_url(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.url)
+    }
+}
+
+/// A [`ClientMessage`] addressed to the call in a specific guild.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub guild: NonZeroU64,
+    pub message: ClientMessage,
+}
+
+/// Sent back to the client once for every [`Request`], in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Error(String),
+    Queue(QueueSnapshot),
+    SearchResults(Vec<SearchResult>),
+    /// Pushed whenever something happens in a guild the client subscribed to.
+    Event {
+        guild: NonZeroU64,
+        message: ServerMessage,
+    },
+}
+
+/// Formats a duration the way the bot displays it, e.g. `42s`, `3:07` or `1:02:03`.
+pub fn format_duration(x: Duration) -> String {
+    let secs = x.as_secs();
+    let mins = secs / 60;
+    let hours = mins / 60;
+    let mins = mins % 60;
+    let secs = secs % 60;
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, mins, secs)
+    } else if mins > 0 {
+        format!("{}:{:02}", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
@@ -8,6 +8,16 @@ use serde::Deserialize;
 struct Config {
     mode: String,
     owner_id: u64,
+    ws_addr: Option<String>,
+    http_addr: Option<String>,
+    public_url: Option<String>,
+    library_dirs: Option<Vec<String>>,
+    yt_dlp_path: Option<String>,
+    yt_dlp_args: Option<Vec<String>>,
+    yt_dlp_timeout: Option<u64>,
+    yt_dlp_max_processes: Option<usize>,
+    yt_dlp_background_processes: Option<usize>,
+    mpd: Option<Mpd>,
     debug: Option<Profile>,
     release: Option<Profile>,
 }
@@ -31,13 +41,53 @@ impl Config {
     fn mk_command(&self) -> Command {
         let mut c = Command::new(self.path());
         c.env("DISCORD_TOKEN", &self.profile().token)
-            .env("BOT_OWNER_ID", &self.owner_id.to_string())
+            .env("BOT_OWNER_ID", self.owner_id.to_string())
             .env("IS_RUN_BY_RUNNER", "1")
             .stdout(Stdio::piped());
+        if let Some(addr) = &self.ws_addr {
+            c.env("WS_ADDR", addr);
+        }
+        if let Some(addr) = &self.http_addr {
+            c.env("HTTP_ADDR", addr);
+        }
+        if let Some(url) = &self.public_url {
+            c.env("PUBLIC_URL", url);
+        }
+        if let Some(dirs) = &self.library_dirs {
+            c.env(
+                "LIBRARY_DIRS",
+                std::env::join_paths(dirs).expect("invalid library directory"),
+            );
+        }
+        if let Some(path) = &self.yt_dlp_path {
+            c.env("YT_DLP_PATH", path);
+        }
+        if let Some(args) = &self.yt_dlp_args {
+            c.env("YT_DLP_ARGS", args.join(" "));
+        }
+        if let Some(secs) = self.yt_dlp_timeout {
+            c.env("YT_DLP_TIMEOUT", secs.to_string());
+        }
+        if let Some(max) = self.yt_dlp_max_processes {
+            c.env("YT_DLP_MAX_PROCESSES", max.to_string());
+        }
+        if let Some(max) = self.yt_dlp_background_processes {
+            c.env("YT_DLP_BACKGROUND_PROCESSES", max.to_string());
+        }
+        if let Some(mpd) = &self.mpd {
+            c.env("MPD_ADDR", &mpd.addr)
+                .env("MPD_GUILD", mpd.guild.to_string());
+        }
         c
     }
 }
 
+#[derive(Deserialize)]
+struct Mpd {
+    addr: String,
+    guild: u64,
+}
+
 #[derive(Deserialize)]
 struct Profile {
     token: String,
@@ -0,0 +1,174 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use async_trait::async_trait;
+use poise::serenity_prelude::{Attachment as DiscordAttachment, Message};
+use reqwest::header::RANGE;
+use reqwest::Client;
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, Input};
+use symphonia::core::io::MediaSource;
+use symphonia::core::probe::Hint;
+
+use crate::file::probe_tags;
+use crate::play::{play_multiple, Queueable};
+use crate::vc::enter_vc;
+use crate::{CommandResult, Context};
+
+crate::commands!(playattachment, play_attachments);
+
+/// Attachments larger than this are not downloaded.
+const MAX_SIZE: u64 = 200 * 1024 * 1024;
+
+/// How much of the start of a file is read to find its tags.
+const TAG_PREFIX: u64 = 2 * 1024 * 1024;
+
+/// A file uploaded to Discord, downloaded into memory when it starts playing.
+pub struct Attachment {
+    client: Client,
+    url: String,
+    filename: String,
+}
+
+impl Attachment {
+    pub fn new(client: Client, url: String, filename: String) -> Self {
+        Attachment {
+            client,
+            url,
+            filename,
+        }
+    }
+
+    fn hint(&self) -> Hint {
+        let mut hint = Hint::new();
+        if let Some(ext) = Path::new(&self.filename)
+            .extension()
+            .and_then(|e| e.to_str())
+        {
+            hint.with_extension(ext);
+        }
+        hint
+    }
+
+    /// Downloads up to `limit` bytes of the attachment. With `partial`, the
+    /// rest is skipped, otherwise a larger attachment is an error.
+    async fn download(&self, limit: u64, partial: bool) -> Result<Vec<u8>, AudioStreamError> {
+        let fail = |e: reqwest::Error| AudioStreamError::Fail(e.into());
+        let mut request = self.client.get(&self.url);
+        if partial {
+            request = request.header(RANGE, format!("bytes=0-{}", limit - 1));
+        }
+        let mut response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(fail)?;
+        let too_large = || AudioStreamError::Fail("attachment is too large".into());
+        if !partial && response.content_length().is_some_and(|len| len > limit) {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        // servers may leave out the length or ignore the range, so the limit is
+        // checked while reading
+        while let Some(chunk) = response.chunk().await.map_err(fail)? {
+            let room = limit as usize - bytes.len();
+            if chunk.len() > room {
+                if !partial {
+                    return Err(too_large());
+                }
+                bytes.extend_from_slice(&chunk[..room]);
+                break;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+}
+
+impl From<Attachment> for Input {
+    fn from(attachment: Attachment) -> Self {
+        Input::Lazy(Box::new(attachment))
+    }
+}
+
+#[async_trait]
+impl Compose for Attachment {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Fail(
+            "attachments should be downloaded asynchronously".into(),
+        ))
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let bytes = self.download(MAX_SIZE, false).await?;
+        Ok(AudioStream {
+            input: Box::new(Cursor::new(bytes)),
+            hint: Some(self.hint()),
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    /// Reads tags from the start of the file, which is dropped once they are read.
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        let prefix = self.download(TAG_PREFIX, true).await?;
+        let hint = self.hint();
+        // files with their tags at the end are titled by their name instead
+        let tags =
+            tokio::task::spawn_blocking(move || probe_tags(Box::new(Cursor::new(prefix)), &hint))
+                .await
+                .unwrap()
+                .unwrap_or_default();
+        Ok(tags.into_metadata(Path::new(&self.filename), self.url.clone()))
+    }
+}
+
+/// Whether `attachment` could be audio. Ones without a content type are given a try.
+fn maybe_audio(attachment: &DiscordAttachment) -> bool {
+    attachment
+        .content_type
+        .as_deref()
+        .is_none_or(|ty| ty.starts_with("audio/") || ty.starts_with("video/"))
+}
+
+async fn play_common(ctx: Context<'_>, attachments: Vec<DiscordAttachment>) -> CommandResult {
+    let inputs = attachments
+        .into_iter()
+        .filter(maybe_audio)
+        .map(|a| Queueable::Attachment {
+            url: a.url,
+            filename: a.filename,
+        })
+        .collect::<Vec<_>>();
+    if inputs.is_empty() {
+        ctx.say("No audio files are attached").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+    enter_vc(ctx, true, |handler, ctx| async move {
+        play_multiple(ctx, inputs, &handler).await
+    })
+    .await
+}
+
+#[poise::command(slash_command, guild_only, category = "Enqueue")]
+/// Add an uploaded audio file to the queue.
+pub async fn playattachment(
+    ctx: Context<'_>,
+    #[description = "audio file to play"] file: DiscordAttachment,
+) -> CommandResult {
+    play_common(ctx, vec![file]).await
+}
+
+#[poise::command(
+    context_menu_command = "Play attachments",
+    guild_only,
+    category = "Enqueue"
+)]
+/// Add the audio files attached to a message to the queue.
+pub async fn play_attachments(ctx: Context<'_>, message: Message) -> CommandResult {
+    play_common(ctx, message.attachments).await
+}
@@ -0,0 +1,391 @@
+use std::env;
+use std::num::NonZeroU64;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::bail;
+use poise::serenity_prelude::Permissions;
+use poise::{ChoiceParameter, CreateReply};
+use rand::distr::Alphanumeric;
+use rand::{rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use songbird::id::GuildId;
+use subtle::ConstantTimeEq;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::ws::ClientMessage;
+use crate::{CommandResult, Context, Error};
+
+crate::commands!(token);
+
+/// What a token is allowed to do within its guild.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ChoiceParameter,
+)]
+pub enum Scope {
+    /// Look at the queue and receive events.
+    #[name = "read-only"]
+    Read,
+    /// Everything `Read` can do, plus changing playback and the queue.
+    #[name = "control"]
+    Control,
+}
+
+/// The scope a token needs to send `message`.
+pub fn required_scope(message: &ClientMessage) -> Scope {
+    use ClientMessage::*;
+    match message {
+        Subscribe | Unsubscribe | QueueSnapshot => Scope::Read,
+        ClearAll
+        | Pause
+        | Resume
+        | Skip
+        | Seek(_)
+        | Enqueue(_)
+        | Move { .. }
+        | Swap { .. }
+        | Remove(_)
+        | Shuffle
+        | ToggleLoop
+        | Join { .. }
+        | Leave
+        | Search { .. } => Scope::Control,
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Token {
+    pub id: u64,
+    /// SHA-256 of the secret, hex encoded. The secret itself is only shown
+    /// once, when the token is issued.
+    pub hash: String,
+    pub guild: NonZeroU64,
+    pub scope: Scope,
+    pub issued_by: NonZeroU64,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl Token {
+    /// Fails unless this token may act on `guild` with at least `scope`.
+    pub fn authorize(&self, guild: GuildId, scope: Scope) -> Result<(), Error> {
+        if self.guild != guild.0 {
+            bail!("token is not valid for this guild");
+        }
+        if self.scope < scope {
+            bail!("token is read-only");
+        }
+        Ok(())
+    }
+}
+
+/// How long a dashboard login stays valid.
+const SESSION_LENGTH: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// What is saved to the tokens file.
+#[derive(Serialize)]
+struct Tokens {
+    /// Ids aren't reused after a token is revoked, so a client can't end up
+    /// shown as a newer token.
+    next_id: u64,
+    tokens: Vec<Token>,
+}
+
+/// Tokens for remote control clients, persisted as JSON.
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: Mutex<Tokens>,
+    /// Held while saving, so that saves don't interleave their writes.
+    saving: tokio::sync::Mutex<()>,
+    /// Short-lived tokens handed out to the dashboard, kept in memory only.
+    sessions: Mutex<Vec<(Token, Instant)>>,
+}
+
+impl TokenStore {
+    /// Loads the tokens from `TOKENS_PATH`, defaulting to `./tokens.json`.
+    pub async fn load() -> color_eyre::Result<Arc<Self>> {
+        let path = PathBuf::from(env::var("TOKENS_PATH").unwrap_or("./tokens.json".into()));
+        let saved: Value = match fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Value::Array(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        // files from before ids were tracked only hold the list of tokens
+        let (next_id, saved) = match saved {
+            Value::Array(saved) => (None, saved),
+            mut saved => match saved.get_mut("tokens").map(Value::take) {
+                Some(Value::Array(tokens)) => (saved["next_id"].as_u64(), tokens),
+                _ => bail!("{} is not a tokens file", path.display()),
+            },
+        };
+        let mut migrated = next_id.is_none() && !saved.is_empty();
+        let mut tokens = Vec::with_capacity(saved.len());
+        for mut token in saved {
+            // tokens issued before secrets were hashed
+            if let Some(Value::String(secret)) =
+                token.as_object_mut().and_then(|t| t.remove("secret"))
+            {
+                token["hash"] = hash_secret(&secret).into();
+                migrated = true;
+            }
+            tokens.push(serde_json::from_value::<Token>(token)?);
+        }
+        let next_id = tokens
+            .iter()
+            .map(|t| t.id + 1)
+            .chain(next_id)
+            .max()
+            .unwrap_or(1);
+        let store = TokenStore {
+            path,
+            tokens: Mutex::new(Tokens { next_id, tokens }),
+            saving: tokio::sync::Mutex::new(()),
+            sessions: Mutex::new(Vec::new()),
+        };
+        if migrated {
+            store.save().await?;
+        }
+        Ok(Arc::new(store))
+    }
+
+    pub fn get(&self, secret: &str) -> Option<Token> {
+        self.find(&hash_secret(secret))
+    }
+
+    /// `token` as it is now, or `None` once it was revoked or has expired.
+    pub fn current(&self, token: &Token) -> Option<Token> {
+        self.find(&token.hash)
+    }
+
+    fn find(&self, hash: &str) -> Option<Token> {
+        let matches = |t: &Token| bool::from(t.hash.as_bytes().ct_eq(hash.as_bytes()));
+        if let Some(token) = self
+            .tokens
+            .lock()
+            .unwrap()
+            .tokens
+            .iter()
+            .find(|t| matches(t))
+        {
+            return Some(token.clone());
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|(_, expires)| *expires > Instant::now());
+        sessions
+            .iter()
+            .find(|(t, _)| matches(t))
+            .map(|(t, _)| t.clone())
+    }
+
+    pub fn list(&self, guild: GuildId) -> Vec<Token> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .tokens
+            .iter()
+            .filter(|t| t.guild == guild.0)
+            .cloned()
+            .collect()
+    }
+
+    /// Issues a control token for `user` that expires after [`SESSION_LENGTH`],
+    /// returning its secret.
+    pub fn start_session(&self, guild: GuildId, user: NonZeroU64) -> String {
+        let secret = random_secret();
+        let token = Token {
+            id: 0,
+            hash: hash_secret(&secret),
+            guild: guild.0,
+            scope: Scope::Control,
+            issued_by: user,
+            label: Some("dashboard".into()),
+        };
+        self.sessions
+            .lock()
+            .unwrap()
+            .push((token, Instant::now() + SESSION_LENGTH));
+        secret
+    }
+
+    /// Returns the new token along with its secret.
+    pub async fn issue(
+        &self,
+        guild: GuildId,
+        scope: Scope,
+        issued_by: NonZeroU64,
+        label: Option<String>,
+    ) -> color_eyre::Result<(Token, String)> {
+        let secret = random_secret();
+        let token = {
+            let mut tokens = self.tokens.lock().unwrap();
+            let token = Token {
+                id: tokens.next_id,
+                hash: hash_secret(&secret),
+                guild: guild.0,
+                scope,
+                issued_by,
+                label,
+            };
+            tokens.next_id += 1;
+            tokens.tokens.push(token.clone());
+            token
+        };
+        self.save().await?;
+        Ok((token, secret))
+    }
+
+    /// Returns whether a token with that id existed in the guild.
+    pub async fn revoke(&self, guild: GuildId, id: u64) -> color_eyre::Result<bool> {
+        let removed = {
+            let tokens = &mut self.tokens.lock().unwrap().tokens;
+            let len = tokens.len();
+            tokens.retain(|t| t.guild != guild.0 || t.id != id);
+            tokens.len() != len
+        };
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    /// Writes the tokens to a file only the bot's user can read. The file is
+    /// replaced at once, so it is never left half written.
+    async fn save(&self) -> color_eyre::Result<()> {
+        let _saving = self.saving.lock().await;
+        // taken after waiting for other saves, so the last one has every change
+        let s = serde_json::to_string_pretty(&*self.tokens.lock().unwrap())?;
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&temp).await?;
+        // the mode only applies to new files
+        #[cfg(unix)]
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await?;
+        file.write_all(s.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&temp, &self.path).await?;
+        Ok(())
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+pub fn random_secret() -> String {
+    rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect()
+}
+
+pub async fn owner_or_admin(ctx: Context<'_>) -> Result<bool, Error> {
+    if ctx.framework().options().owners.contains(&ctx.author().id) {
+        return Ok(true);
+    }
+    let permissions = match ctx.author_member().await {
+        Some(member) => ctx
+            .guild()
+            .map_or(Permissions::empty(), |g| g.member_permissions(&member)),
+        None => Permissions::empty(),
+    };
+    if permissions.administrator() {
+        Ok(true)
+    } else {
+        ctx.say("Can only be used by the bot owner or server administrators")
+            .await?;
+        Ok(false)
+    }
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Remote control",
+    subcommands("issue", "list", "revoke")
+)]
+/// Manage tokens for remote control clients
+pub async fn token(_ctx: Context<'_>) -> CommandResult {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, check = "owner_or_admin")]
+/// Issue a new token for this server
+async fn issue(
+    ctx: Context<'_>,
+    #[description = "what the token is allowed to do"] scope: Scope,
+    #[description = "a name to recognize the token by"] label: Option<String>,
+) -> CommandResult {
+    let guild = ctx.guild_id().unwrap().into();
+    let issued_by = NonZeroU64::new(ctx.author().id.get()).unwrap();
+    let (token, secret) = ctx
+        .data()
+        .tokens
+        .issue(guild, scope, issued_by, label)
+        .await?;
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Issued token {} with {} access: `{secret}`",
+                token.id,
+                scope.name(),
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, check = "owner_or_admin")]
+/// List tokens issued for this server
+async fn list(ctx: Context<'_>) -> CommandResult {
+    let tokens = ctx.data().tokens.list(ctx.guild_id().unwrap().into());
+    let text = if tokens.is_empty() {
+        "No tokens issued".to_string()
+    } else {
+        tokens
+            .iter()
+            .map(|t| {
+                format!(
+                    "{}: {} ({}), issued by <@{}>",
+                    t.id,
+                    t.label.as_deref().unwrap_or("unnamed"),
+                    t.scope.name(),
+                    t.issued_by
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    ctx.send(CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, check = "owner_or_admin")]
+/// Revoke a token issued for this server
+async fn revoke(
+    ctx: Context<'_>,
+    #[description = "id of the token, as shown by /token list"] id: u64,
+) -> CommandResult {
+    let guild = ctx.guild_id().unwrap().into();
+    let text = if ctx.data().tokens.revoke(guild, id).await? {
+        format!("Revoked token {id}")
+    } else {
+        format!("No token with id {id}")
+    };
+    ctx.send(CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
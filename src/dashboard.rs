@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use hmac::{Hmac, Mac};
+use poise::serenity_prelude::CreateMessage;
+use poise::CreateReply;
+use rand::{rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use songbird::id::GuildId;
+
+use crate::auth::{owner_or_admin, random_secret, TokenStore};
+use crate::http::ApiError;
+use crate::{CommandResult, Context};
+
+crate::commands!(dashboard);
+
+/// How long a login link can be used for, in seconds.
+const LINK_LIFETIME: u64 = 10 * 60;
+
+/// Signs one-time dashboard login links and redeems them for session tokens.
+pub struct Dashboard {
+    /// Base URL of the HTTP server as users reach it, from `PUBLIC_URL`.
+    public_url: Option<String>,
+    /// Regenerated on every start, so restarting invalidates unused links.
+    key: [u8; 32],
+    /// Nonces of redeemed links, with the time their link expires.
+    used: Mutex<HashMap<String, u64>>,
+    tokens: Arc<TokenStore>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl Dashboard {
+    pub fn new(public_url: Option<String>, tokens: Arc<TokenStore>) -> Arc<Self> {
+        Arc::new(Dashboard {
+            public_url: public_url.map(|url| url.trim_end_matches('/').to_owned()),
+            key: rng().random(),
+            used: Mutex::new(HashMap::new()),
+            tokens,
+        })
+    }
+
+    fn signature(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// Creates a login link for `user` in `guild`, or `None` if `PUBLIC_URL` isn't set.
+    pub fn link(&self, guild: GuildId, user: NonZeroU64) -> Option<String> {
+        let url = self.public_url.as_ref()?;
+        let nonce = &random_secret()[..16];
+        let payload = format!("{}.{user}.{}.{nonce}", guild.0, now() + LINK_LIFETIME);
+        let signature = hex::encode(self.signature(&payload).finalize().into_bytes());
+        Some(format!("{url}/dashboard#login={payload}.{signature}"))
+    }
+
+    /// Checks a code from [`Dashboard::link`] and returns its guild and user,
+    /// unless it has expired or was already used.
+    fn redeem(&self, code: &str) -> Option<(GuildId, NonZeroU64)> {
+        let (payload, signature) = code.rsplit_once('.')?;
+        self.signature(payload)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+        let mut parts = payload.split('.');
+        let guild = parts.next()?.parse().ok()?;
+        let user = parts.next()?.parse().ok()?;
+        let expires: u64 = parts.next()?.parse().ok()?;
+        let nonce = parts.next()?;
+        let now = now();
+        if expires < now {
+            return None;
+        }
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, expires| *expires >= now);
+        if used.insert(nonce.to_owned(), expires).is_some() {
+            return None;
+        }
+        Some((GuildId(guild), user))
+    }
+}
+
+/// The dashboard page and its login endpoint.
+pub fn router(dashboard: Arc<Dashboard>) -> Router {
+    Router::new()
+        .route("/dashboard", get(page))
+        .route("/dashboard/login", post(login))
+        .with_state(dashboard)
+}
+
+async fn page() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+#[derive(Deserialize)]
+struct Login {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct Session {
+    token: String,
+    /// A string, since guild ids don't fit in a JavaScript number.
+    guild: String,
+}
+
+async fn login(
+    State(dashboard): State<Arc<Dashboard>>,
+    Json(Login { code }): Json<Login>,
+) -> Result<Json<Session>, ApiError> {
+    let (guild, user) = dashboard.redeem(&code).ok_or(ApiError::Unauthorized)?;
+    let token = dashboard.tokens.start_session(guild, user);
+    Ok(Json(Session {
+        token,
+        guild: guild.0.to_string(),
+    }))
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Remote control",
+    check = "owner_or_admin"
+)]
+/// Get a link to control this server's queue from your browser
+pub async fn dashboard(ctx: Context<'_>) -> CommandResult {
+    let guild = ctx.guild_id().unwrap().into();
+    let user = NonZeroU64::new(ctx.author().id.get()).unwrap();
+    let Some(link) = ctx.data().dashboard.link(guild, user) else {
+        ctx.say("The dashboard is not enabled on this bot").await?;
+        return Ok(());
+    };
+    let message = CreateMessage::new().content(format!(
+        "Open the dashboard: {link}\nThe link works once and expires in 10 minutes."
+    ));
+    let text = match ctx.author().dm(ctx, message).await {
+        Ok(_) => "Sent you a link by DM",
+        Err(_) => "Couldn't send you a DM. Do you allow messages from server members?",
+    };
+    ctx.send(CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::{CONTENT_TYPE, RANGE};
+use reqwest::{Client, Url};
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, HttpRequest, Input};
+use symphonia::core::io::MediaSource;
+use symphonia::core::probe::Hint;
+use tokio::time::timeout;
+
+use crate::file::probe_tags;
+use crate::library::is_audio;
+
+/// Sites yt-dlp knows, whose pages are never audio files themselves.
+const YT_DLP_HOSTS: &[&str] = &[
+    "youtube.com",
+    "youtu.be",
+    "soundcloud.com",
+    "bandcamp.com",
+    "vimeo.com",
+    "twitch.tv",
+];
+
+/// Whether `url` points straight at an audio file, going by the URL when it
+/// tells, or `None` if the server has to be asked.
+fn guess_direct_audio(url: &str) -> Option<bool> {
+    let Ok(url) = Url::parse(url) else {
+        // search terms and the like
+        return Some(false);
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return Some(false);
+    }
+    let host = url.host_str().unwrap_or_default();
+    let known = YT_DLP_HOSTS
+        .iter()
+        .any(|site| host == *site || host.ends_with(&format!(".{site}")));
+    if known {
+        Some(false)
+    } else if is_audio(Path::new(url.path())) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Whether `url` points straight at an audio file, judging by its extension
+/// or the content type the server reports. Anything else is left to yt-dlp.
+pub async fn is_direct_audio(client: &Client, url: &str) -> bool {
+    if let Some(direct) = guess_direct_audio(url) {
+        return direct;
+    }
+    // some servers reject HEAD, but all of them can send the first byte
+    let request = client.get(url).header(RANGE, "bytes=0-0").send();
+    let Ok(Ok(response)) = timeout(Duration::from_secs(5), request).await else {
+        return false;
+    };
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ty| ty.to_str().ok())
+        .unwrap_or_default();
+    response.status().is_success()
+        && (content_type.starts_with("audio/") || content_type.starts_with("application/ogg"))
+}
+
+/// An audio file streamed over HTTP with range requests.
+pub struct DirectAudio {
+    request: HttpRequest,
+}
+
+impl DirectAudio {
+    pub fn new(client: Client, url: String) -> Self {
+        DirectAudio {
+            request: HttpRequest::new(client, url),
+        }
+    }
+
+    /// The last path segment of the URL, to title untagged files with.
+    fn file_name(&self) -> String {
+        let url = self
+            .request
+            .request
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        url.rsplit('/').next().unwrap_or(url).to_owned()
+    }
+}
+
+impl From<DirectAudio> for Input {
+    fn from(audio: DirectAudio) -> Self {
+        Input::Lazy(Box::new(audio))
+    }
+}
+
+#[async_trait]
+impl Compose for DirectAudio {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.request.create()
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.request.create_async().await
+    }
+
+    fn should_create_async(&self) -> bool {
+        self.request.should_create_async()
+    }
+
+    /// Reads tags from the start of the file, which is dropped once they are read.
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        let AudioStream { input, hint } = self.request.create_async().await?;
+        let file_name = self.file_name();
+        let url = self.request.request.clone();
+        let tags =
+            tokio::task::spawn_blocking(move || probe_tags(input, &hint.unwrap_or_else(Hint::new)))
+                .await
+                .unwrap()
+                .unwrap_or_default();
+        Ok(tags.into_metadata(Path::new(&file_name), url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::guess_direct_audio;
+
+    #[test]
+    fn guesses_from_the_url() {
+        assert_eq!(
+            guess_direct_audio("https://example.com/a/song.MP3?x=1"),
+            Some(true)
+        );
+        assert_eq!(
+            guess_direct_audio("https://www.youtube.com/watch?v=a"),
+            Some(false)
+        );
+        assert_eq!(
+            guess_direct_audio("https://artist.bandcamp.com/track/a.mp3"),
+            Some(false)
+        );
+        assert_eq!(guess_direct_audio("never gonna give you up"), Some(false));
+        assert_eq!(guess_direct_audio("https://example.com/stream"), None);
+    }
+}
@@ -0,0 +1,795 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use songbird::input::{
+    AudioStream, AudioStreamError, AuxMetadata, AuxMetadataError, Compose, HlsRequest, HttpRequest,
+    Input,
+};
+use symphonia::core::io::MediaSource;
+use tokio::process::Command;
+use tokio::sync::{watch, Notify, Semaphore, SemaphorePermit};
+use tokio::time::timeout;
+use tokio::{pin, select};
+use tracing::{debug, warn};
+
+use crate::Error;
+
+/// An entry yt-dlp lists for a search or playlist.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Output {
+    pub url: String,
+    /// Which yt-dlp extractor the entry is for, like `Youtube` or `YoutubePlaylist`.
+    pub ie_key: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Set instead of `channel` by sites like SoundCloud.
+    #[serde(default)]
+    pub uploader: Option<String>,
+    /// In seconds.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    /// `url` for entries that still need resolving, which is all of them with `--flat-playlist`.
+    #[serde(rename = "_type")]
+    pub kind: String,
+}
+
+impl Output {
+    pub fn is_playable(&self) -> bool {
+        self.kind == "url" && !self.is_playlist()
+    }
+    pub fn is_playlist(&self) -> bool {
+        self.ie_key == "YoutubePlaylist" || self.ie_key == "YoutubeTab"
+    }
+
+    /// What the listing already tells about the entry, until it is resolved.
+    pub fn metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            title: self.title.clone(),
+            artist: self.channel.clone().or_else(|| self.uploader.clone()),
+            channel: self.channel.clone(),
+            duration: self.duration.map(Duration::from_secs_f64),
+            source_url: Some(self.url.clone()),
+            ..AuxMetadata::default()
+        }
+    }
+}
+
+/// Why yt-dlp couldn't list or play something, worded for users.
+#[derive(Debug)]
+pub enum ExtractorError {
+    Private,
+    GeoBlocked,
+    AgeRestricted,
+    SignInRequired,
+    Unsupported,
+    RateLimited,
+    Unavailable,
+    NoResults,
+    TimedOut,
+    /// The binary couldn't be started.
+    NotInstalled(String),
+    /// Anything else, with yt-dlp's own message.
+    Failed {
+        code: Option<i32>,
+        message: String,
+    },
+}
+
+impl ExtractorError {
+    /// Sorts a failed run by what yt-dlp printed to stderr.
+    pub fn from_stderr(code: Option<i32>, stderr: &str) -> Self {
+        let lower = stderr.to_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| lower.contains(n));
+        // age checks also ask to sign in, so they go first
+        if has(&["private video", "video is private"]) {
+            ExtractorError::Private
+        } else if has(&[
+            "age-restricted",
+            "confirm your age",
+            "inappropriate for some users",
+        ]) {
+            ExtractorError::AgeRestricted
+        } else if has(&["available in your country", "geo restrict", "geo-restrict"]) {
+            ExtractorError::GeoBlocked
+        } else if has(&[
+            "http error 429",
+            "too many requests",
+            "rate-limit",
+            "rate limit",
+        ]) {
+            ExtractorError::RateLimited
+        } else if has(&["sign in", "login required", "log in", "use --cookies"]) {
+            ExtractorError::SignInRequired
+        } else if has(&["unsupported url"]) {
+            ExtractorError::Unsupported
+        } else if has(&["video unavailable", "is not available", "has been removed"]) {
+            ExtractorError::Unavailable
+        } else {
+            // the last error is the one that stopped yt-dlp
+            let message = stderr
+                .lines()
+                .rev()
+                .find_map(|line| line.strip_prefix("ERROR: "))
+                .or_else(|| stderr.lines().rev().find(|line| !line.trim().is_empty()))
+                .unwrap_or("no error message")
+                .to_owned();
+            ExtractorError::Failed { code, message }
+        }
+    }
+
+    fn from_io(program: &str, e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            ExtractorError::NotInstalled(program.to_owned())
+        } else {
+            ExtractorError::Failed {
+                code: None,
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExtractorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractorError::Private => f.write_str("This video is private."),
+            ExtractorError::GeoBlocked => f.write_str("This video isn't available in the bot's country."),
+            ExtractorError::AgeRestricted => f.write_str(
+                "This video is age-restricted. The bot needs cookies of a signed-in account to play it.",
+            ),
+            ExtractorError::SignInRequired => f.write_str(
+                "The site wants the bot to sign in first. Passing --cookies in yt_dlp_args can help.",
+            ),
+            ExtractorError::Unsupported => f.write_str("yt-dlp doesn't support this URL."),
+            ExtractorError::RateLimited => {
+                f.write_str("The site is rate limiting the bot, try again later.")
+            }
+            ExtractorError::Unavailable => f.write_str("This video is unavailable."),
+            ExtractorError::NoResults => f.write_str("The search found nothing."),
+            ExtractorError::TimedOut => f.write_str("yt-dlp took too long and was stopped."),
+            ExtractorError::NotInstalled(program) => {
+                write!(f, "Could not run {program}, is yt-dlp installed?")
+            }
+            ExtractorError::Failed {
+                code: Some(code),
+                message,
+            } => write!(f, "yt-dlp failed with exit code {code}: {message}"),
+            ExtractorError::Failed {
+                code: None,
+                message,
+            } => write!(f, "yt-dlp failed: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ExtractorError {}
+
+/// Finds and plays media on sites like YouTube. Everything that goes through
+/// yt-dlp uses this, so it can be swapped for another implementation.
+#[async_trait]
+pub trait Extractor: Send + Sync {
+    /// Lists up to `num` results of `query`, which is a search prefix like
+    /// `ytsearch5:term` or the URL of a search page.
+    async fn search(&self, query: &str, num: usize) -> Result<Vec<Output>, Error>;
+
+    /// Lists the entries of a playlist without resolving each of them.
+    /// `range` picks entries like yt-dlp's `-I`, e.g. `1,3,5:6`.
+    async fn flat_playlist(&self, url: &str, range: Option<&str>) -> Result<Vec<Output>, Error>;
+
+    /// An input that plays `arg`, a URL or a search prefix like `ytsearch1:term`.
+    fn resolve(&self, client: Client, arg: String) -> Input;
+
+    /// The metadata of what `arg` plays, looked up in the background with a
+    /// lower priority than requests someone is waiting for.
+    async fn lookup(&self, arg: &str) -> Result<AuxMetadata, Error>;
+}
+
+tokio::task_local! {
+    /// Where a request waiting for a free yt-dlp process reports its place in line.
+    pub static PLACE_IN_LINE: watch::Sender<usize>;
+
+    /// Which share of the yt-dlp processes searches count against, if not
+    /// [`Budget::Commands`].
+    pub static SEARCH_BUDGET: Budget;
+}
+
+/// Caps how many processes run at once, letting waiting requests through in order.
+struct Slots {
+    semaphore: Semaphore,
+    /// Tickets of the waiting requests, oldest first.
+    line: Mutex<VecDeque<u64>>,
+    next_ticket: AtomicU64,
+    /// Woken whenever someone leaves the line.
+    moved: Notify,
+}
+
+impl Slots {
+    fn new(max: usize) -> Self {
+        Slots {
+            semaphore: Semaphore::new(max),
+            line: Mutex::new(VecDeque::new()),
+            next_ticket: AtomicU64::new(0),
+            moved: Notify::new(),
+        }
+    }
+
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        if let Ok(permit) = self.semaphore.try_acquire() {
+            return permit;
+        }
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.line.lock().unwrap().push_back(ticket);
+        let _leave = Leave {
+            slots: self,
+            ticket,
+        };
+        // the semaphore is fair, so it lets requests through in the same order as `line`
+        let acquire = self.semaphore.acquire();
+        pin!(acquire);
+        loop {
+            let moved = self.moved.notified();
+            let place = self
+                .line
+                .lock()
+                .unwrap()
+                .iter()
+                .position(|&t| t == ticket)
+                .unwrap_or_default();
+            let _ = PLACE_IN_LINE.try_with(|tx| tx.send_replace(place + 1));
+            select! {
+                permit = &mut acquire => return permit.expect("the semaphore is never closed"),
+                () = moved => {}
+            }
+        }
+    }
+}
+
+/// Takes a request out of the line once it runs, or when it is abandoned.
+struct Leave<'a> {
+    slots: &'a Slots,
+    ticket: u64,
+}
+
+impl Drop for Leave<'_> {
+    fn drop(&mut self) {
+        let ticket = self.ticket;
+        self.slots.line.lock().unwrap().retain(|&t| t != ticket);
+        self.slots.moved.notify_waiters();
+    }
+}
+
+/// Which share of the yt-dlp processes a run counts against.
+#[derive(Clone, Copy, Debug)]
+pub enum Budget {
+    /// Requests someone is waiting for, limited by `YT_DLP_MAX_PROCESSES`.
+    Commands,
+    /// Starting the next song, which is never held up by other requests.
+    Playback,
+    /// Lookups nobody waits for, limited by `YT_DLP_BACKGROUND_PROCESSES`
+    /// on top of the processes for commands.
+    Background,
+    /// Searches while typing, which get [`SUGGESTION_PROCESSES`] of their own.
+    Suggestions,
+}
+
+/// How many processes autocomplete searches can run at once, on top of the ones for commands.
+const SUGGESTION_PROCESSES: usize = 1;
+
+/// Starts yt-dlp processes, so that none of them run forever or all at once.
+struct Runner {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+    commands: Slots,
+    background: Slots,
+    suggestions: Slots,
+}
+
+impl Runner {
+    /// Runs yt-dlp with `args` after the configured ones once `budget` has a
+    /// free slot, returning what it printed. The process is killed if this is dropped.
+    async fn run(&self, args: &[&str], budget: Budget) -> Result<Vec<u8>, ExtractorError> {
+        let _permit = match budget {
+            Budget::Commands => Some(self.commands.acquire().await),
+            Budget::Background => Some(self.background.acquire().await),
+            Budget::Suggestions => Some(self.suggestions.acquire().await),
+            Budget::Playback => None,
+        };
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .args(args)
+            .stderr(Stdio::piped())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = timeout(self.timeout, output)
+            .await
+            .map_err(|_| ExtractorError::TimedOut)?
+            .map_err(|e| ExtractorError::from_io(&self.program, e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(ExtractorError::from_stderr(output.status.code(), &stderr));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Asks yt-dlp where the audio of `arg` is, and what it is.
+    async fn resolve(&self, arg: &str, budget: Budget) -> Result<Resolved, ExtractorError> {
+        let args = [
+            "-j",
+            arg,
+            "-f",
+            "ba[abr>0][vcodec=none]/best",
+            "--no-playlist",
+        ];
+        let stdout = self.run(&args, budget).await?;
+        parse_lines::<Resolved>(&stdout)
+            .map_err(|e| ExtractorError::Failed {
+                code: None,
+                message: format!("unexpected output: {e}"),
+            })?
+            .into_iter()
+            .next()
+            .ok_or(ExtractorError::NoResults)
+    }
+}
+
+/// Parses the JSON object yt-dlp prints on each line.
+fn parse_lines<T: DeserializeOwned>(stdout: &[u8]) -> Result<Vec<T>, serde_json::Error> {
+    stdout
+        .split(|x| *x == b'\n')
+        .filter(|bytes| !bytes.is_empty())
+        .map(serde_json::from_slice)
+        .collect()
+}
+
+/// The yt-dlp binary.
+pub struct YtDlp(Arc<Runner>);
+
+impl YtDlp {
+    /// Runs `YT_DLP_PATH`, defaulting to `yt-dlp`, with the whitespace separated
+    /// arguments in `YT_DLP_ARGS`. Without them, `./cookies.firefox-private.txt`
+    /// is passed as cookies if it exists. At most `YT_DLP_MAX_PROCESSES` (4)
+    /// run at once for commands and `YT_DLP_BACKGROUND_PROCESSES` (2) for
+    /// background lookups, each stopped after `YT_DLP_TIMEOUT` (120) seconds.
+    pub fn from_env() -> Self {
+        let program = env::var("YT_DLP_PATH").unwrap_or("yt-dlp".into());
+        let args = match env::var("YT_DLP_ARGS") {
+            Ok(args) => args.split_whitespace().map(str::to_owned).collect(),
+            Err(_) if std::fs::exists("./cookies.firefox-private.txt").unwrap_or(false) => {
+                vec!["--cookies".into(), "./cookies.firefox-private.txt".into()]
+            }
+            Err(_) => Vec::new(),
+        };
+        let number = |key, default| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&n| n > 0)
+                .unwrap_or(default)
+        };
+        YtDlp(Arc::new(Runner {
+            program,
+            args,
+            timeout: Duration::from_secs(number("YT_DLP_TIMEOUT", 120)),
+            commands: Slots::new(number("YT_DLP_MAX_PROCESSES", 4) as usize),
+            background: Slots::new(number("YT_DLP_BACKGROUND_PROCESSES", 2) as usize),
+            suggestions: Slots::new(SUGGESTION_PROCESSES),
+        }))
+    }
+
+    /// Runs yt-dlp with `args`, parsing one entry per line.
+    async fn list(&self, args: &[&str], budget: Budget) -> Result<Vec<Output>, Error> {
+        let stdout = self
+            .0
+            .run(&[&["--flat-playlist", "-s", "-j"], args].concat(), budget)
+            .await?;
+        // one odd entry shouldn't lose the rest of a playlist
+        let outputs = stdout
+            .split(|x| *x == b'\n')
+            .filter(|bytes| !bytes.is_empty())
+            .filter_map(|bytes| match serde_json::from_slice::<Output>(bytes) {
+                Ok(out) => Some(out),
+                Err(e) => {
+                    warn!(%e, "skipping an entry yt-dlp listed");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        debug!(?outputs);
+        Ok(outputs)
+    }
+}
+
+#[async_trait]
+impl Extractor for YtDlp {
+    async fn search(&self, query: &str, num: usize) -> Result<Vec<Output>, Error> {
+        let budget = SEARCH_BUDGET.try_with(|b| *b).unwrap_or(Budget::Commands);
+        self.list(&["--playlist-end", &num.to_string(), query], budget)
+            .await
+    }
+
+    async fn flat_playlist(&self, url: &str, range: Option<&str>) -> Result<Vec<Output>, Error> {
+        match range {
+            Some(range) => self.list(&["-I", range, url], Budget::Commands).await,
+            None => self.list(&[url], Budget::Commands).await,
+        }
+    }
+
+    fn resolve(&self, client: Client, arg: String) -> Input {
+        Input::Lazy(Box::new(YtDlpInput {
+            runner: self.0.clone(),
+            client,
+            arg,
+            metadata: None,
+        }))
+    }
+
+    async fn lookup(&self, arg: &str) -> Result<AuxMetadata, Error> {
+        Ok(self.0.resolve(arg, Budget::Background).await?.metadata())
+    }
+}
+
+/// What yt-dlp prints about a video it resolved.
+#[derive(Deserialize)]
+struct Resolved {
+    url: String,
+    protocol: Option<String>,
+    http_headers: Option<HashMap<String, String>>,
+    filesize: Option<u64>,
+    title: Option<String>,
+    track: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    channel: Option<String>,
+    uploader: Option<String>,
+    release_date: Option<String>,
+    upload_date: Option<String>,
+    duration: Option<f64>,
+    webpage_url: Option<String>,
+    thumbnail: Option<String>,
+}
+
+impl Resolved {
+    fn metadata(&self) -> AuxMetadata {
+        AuxMetadata {
+            track: self.track.clone(),
+            artist: self.artist.clone().or_else(|| self.uploader.clone()),
+            album: self.album.clone(),
+            date: self
+                .release_date
+                .clone()
+                .or_else(|| self.upload_date.clone()),
+            channels: Some(2),
+            channel: self.channel.clone(),
+            duration: self.duration.map(Duration::from_secs_f64),
+            sample_rate: Some(48_000),
+            source_url: self.webpage_url.clone(),
+            title: self.title.clone(),
+            thumbnail: self.thumbnail.clone(),
+            ..AuxMetadata::default()
+        }
+    }
+}
+
+/// Plays what yt-dlp resolves `arg` to, like songbird's own yt-dlp input,
+/// but with its processes going through [`Runner`].
+struct YtDlpInput {
+    runner: Arc<Runner>,
+    client: Client,
+    arg: String,
+    metadata: Option<AuxMetadata>,
+}
+
+impl YtDlpInput {
+    async fn query(&mut self, budget: Budget) -> Result<Resolved, AudioStreamError> {
+        let resolved = self
+            .runner
+            .resolve(&self.arg, budget)
+            .await
+            .map_err(|e| AudioStreamError::Fail(Box::new(e)))?;
+        self.metadata = Some(resolved.metadata());
+        Ok(resolved)
+    }
+}
+
+#[async_trait]
+impl Compose for YtDlpInput {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        // the URL expires, so it is fetched again instead of kept from `aux_metadata`
+        let resolved = self.query(Budget::Playback).await?;
+        let headers = resolved
+            .http_headers
+            .iter()
+            .flatten()
+            .filter_map(|(k, v)| {
+                Some((
+                    HeaderName::from_bytes(k.as_bytes()).ok()?,
+                    HeaderValue::from_str(v).ok()?,
+                ))
+            })
+            .collect::<HeaderMap>();
+        match resolved.protocol.as_deref() {
+            Some("m3u8_native") => {
+                HlsRequest::new_with_headers(self.client.clone(), resolved.url, headers).create()
+            }
+            _ => {
+                let mut request =
+                    HttpRequest::new_with_headers(self.client.clone(), resolved.url, headers);
+                request.content_length = resolved.filesize;
+                request.create_async().await
+            }
+        }
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        if let Some(metadata) = &self.metadata {
+            return Ok(metadata.clone());
+        }
+        Ok(self.query(Budget::Commands).await?.metadata())
+    }
+}
+
+/// Unwraps the error behind a failed metadata lookup, so users see why
+/// instead of just that it failed.
+pub fn metadata_error(e: AuxMetadataError) -> Error {
+    match e {
+        AuxMetadataError::Retrieve(AudioStreamError::Fail(e)) => {
+            match e.downcast::<ExtractorError>() {
+                Ok(e) => (*e).into(),
+                Err(e) => eyre!("Could not load the track: {e}"),
+            }
+        }
+        e => eyre!("Could not load the track: {e}"),
+    }
+}
+
+/// An extractor that answers from a script instead of running yt-dlp, so
+/// code using it can be tested offline.
+#[cfg(test)]
+pub mod fake {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use reqwest::Client;
+    use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, Input};
+    use symphonia::core::io::MediaSource;
+
+    use super::{Extractor, ExtractorError, Output};
+    use crate::Error;
+
+    /// A playable entry, like the ones searches list.
+    pub fn video(url: &str, title: &str) -> Output {
+        Output {
+            url: url.into(),
+            ie_key: "Youtube".into(),
+            title: Some(title.into()),
+            channel: None,
+            uploader: None,
+            duration: None,
+            kind: "url".into(),
+        }
+    }
+
+    /// An entry for a playlist inside a search or playlist.
+    pub fn playlist(url: &str) -> Output {
+        Output {
+            ie_key: "YoutubePlaylist".into(),
+            title: None,
+            ..video(url, "")
+        }
+    }
+
+    /// Answers searches, playlists and resolves with what was scripted for
+    /// the query or URL, and remembers what it was asked.
+    #[derive(Default)]
+    pub struct Scripted {
+        outputs: HashMap<String, Vec<Output>>,
+        failures: HashMap<String, String>,
+        asked: Mutex<Vec<String>>,
+    }
+
+    impl Scripted {
+        /// Lists `outputs` for `query`, a search prefix or URL.
+        pub fn with(mut self, query: &str, outputs: Vec<Output>) -> Self {
+            self.outputs.insert(query.into(), outputs);
+            self
+        }
+
+        /// Fails `query` as if yt-dlp printed `stderr`.
+        pub fn failing(mut self, query: &str, stderr: &str) -> Self {
+            self.failures.insert(query.into(), stderr.into());
+            self
+        }
+
+        /// What was searched, listed or resolved, in order.
+        pub fn asked(&self) -> Vec<String> {
+            self.asked.lock().unwrap().clone()
+        }
+
+        fn answer(&self, query: &str) -> Result<Vec<Output>, ExtractorError> {
+            self.asked.lock().unwrap().push(query.into());
+            if let Some(stderr) = self.failures.get(query) {
+                return Err(ExtractorError::from_stderr(Some(1), stderr));
+            }
+            Ok(self.outputs.get(query).cloned().unwrap_or_default())
+        }
+    }
+
+    #[async_trait]
+    impl Extractor for Scripted {
+        async fn search(&self, query: &str, num: usize) -> Result<Vec<Output>, Error> {
+            let mut outputs = self.answer(query)?;
+            outputs.truncate(num);
+            Ok(outputs)
+        }
+
+        async fn flat_playlist(&self, url: &str, _: Option<&str>) -> Result<Vec<Output>, Error> {
+            Ok(self.answer(url)?)
+        }
+
+        fn resolve(&self, _: Client, arg: String) -> Input {
+            let answer = self
+                .answer(&arg)
+                .and_then(|outputs| outputs.into_iter().next().ok_or(ExtractorError::NoResults));
+            Input::Lazy(Box::new(Resolved(Some(answer))))
+        }
+
+        async fn lookup(&self, arg: &str) -> Result<AuxMetadata, Error> {
+            let output = self.answer(arg)?.into_iter().next();
+            Ok(output.ok_or(ExtractorError::NoResults)?.metadata())
+        }
+    }
+
+    /// Has the metadata of the scripted entry, but no audio.
+    struct Resolved(Option<Result<Output, ExtractorError>>);
+
+    #[async_trait]
+    impl Compose for Resolved {
+        fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+            Err(AudioStreamError::Unsupported)
+        }
+
+        async fn create_async(
+            &mut self,
+        ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+            Err(AudioStreamError::Unsupported)
+        }
+
+        fn should_create_async(&self) -> bool {
+            true
+        }
+
+        async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+            match self.0.take() {
+                Some(Ok(output)) => {
+                    let metadata = output.metadata();
+                    self.0 = Some(Ok(output));
+                    Ok(metadata)
+                }
+                Some(Err(e)) => Err(AudioStreamError::Fail(Box::new(e))),
+                None => Err(AudioStreamError::Unsupported),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(stderr: &str) -> ExtractorError {
+        ExtractorError::from_stderr(Some(1), stderr)
+    }
+
+    #[test]
+    fn sorts_common_failures() {
+        assert!(matches!(
+            classify("ERROR: [youtube] abc: Private video. Sign in if you've been granted access to this video"),
+            ExtractorError::Private
+        ));
+        assert!(matches!(
+            classify("ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate for some users."),
+            ExtractorError::AgeRestricted
+        ));
+        assert!(matches!(
+            classify("ERROR: [youtube] abc: Sign in to confirm you're not a bot. Use --cookies-from-browser or --cookies for the authentication."),
+            ExtractorError::SignInRequired
+        ));
+        assert!(matches!(
+            classify("ERROR: [youtube] abc: The uploader has not made this video available in your country"),
+            ExtractorError::GeoBlocked
+        ));
+        assert!(matches!(
+            classify("ERROR: Unsupported URL: https://example.com/"),
+            ExtractorError::Unsupported
+        ));
+        assert!(matches!(
+            classify("ERROR: unable to download video data: HTTP Error 429: Too Many Requests"),
+            ExtractorError::RateLimited
+        ));
+        assert!(matches!(
+            classify("ERROR: [youtube] abc: Video unavailable"),
+            ExtractorError::Unavailable
+        ));
+    }
+
+    #[test]
+    fn keeps_the_last_error_of_unknown_failures() {
+        let stderr = "WARNING: something odd\nERROR: first\nERROR: [generic] x: what stopped it\n";
+        match ExtractorError::from_stderr(Some(2), stderr) {
+            ExtractorError::Failed { code, message } => {
+                assert_eq!(code, Some(2));
+                assert_eq!(message, "[generic] x: what stopped it");
+            }
+            e => panic!("sorted as {e:?}"),
+        }
+        match ExtractorError::from_stderr(None, "\nTraceback\n  boom\n") {
+            ExtractorError::Failed { message, .. } => assert_eq!(message, "  boom"),
+            e => panic!("sorted as {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolving_reports_why_it_failed() {
+        let extractor = fake::Scripted::default()
+            .with(
+                "https://youtu.be/a",
+                vec![fake::video("https://youtu.be/a", "A")],
+            )
+            .failing("https://youtu.be/b", "ERROR: [youtube] b: Private video");
+        let mut input = extractor.resolve(Client::new(), "https://youtu.be/a".into());
+        let metadata = input.aux_metadata().await.unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("A"));
+
+        let mut input = extractor.resolve(Client::new(), "https://youtu.be/b".into());
+        let error = metadata_error(input.aux_metadata().await.unwrap_err());
+        assert!(matches!(
+            error.downcast_ref::<ExtractorError>(),
+            Some(ExtractorError::Private)
+        ));
+        assert_eq!(
+            extractor.asked(),
+            ["https://youtu.be/a", "https://youtu.be/b"]
+        );
+    }
+
+    #[test]
+    fn parses_listed_entries() {
+        let stdout = br#"{"url": "https://youtu.be/a", "ie_key": "Youtube", "title": "A", "duration": 61.5, "_type": "url"}
+
+{"url": "https://youtube.com/playlist?list=b", "ie_key": "YoutubePlaylist", "_type": "url"}
+"#;
+        let outputs = parse_lines::<Output>(stdout).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs[0].is_playable());
+        assert_eq!(outputs[0].duration, Some(61.5));
+        assert!(outputs[1].is_playlist());
+        assert!(!outputs[1].is_playable());
+    }
+}
@@ -0,0 +1,211 @@
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{bail, eyre};
+use serde::{Deserialize, Serialize};
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, Input};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+use crate::play::{play_common, Queueable};
+use crate::{CommandResult, Context, Error};
+
+crate::commands!(playfile);
+
+/// Directories local files may be played from, from `LIBRARY_DIRS`.
+pub fn library_dirs() -> &'static [PathBuf] {
+    static DIRS: LazyLock<Vec<PathBuf>> = LazyLock::new(|| {
+        env::var_os("LIBRARY_DIRS")
+            .map(|dirs| {
+                env::split_paths(&dirs)
+                    .filter_map(|dir| dir.canonicalize().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    &DIRS
+}
+
+/// Finds `path` in the library. Relative paths are looked up in each library
+/// directory in turn, and nothing outside of them can be opened.
+pub fn resolve(path: &Path) -> Result<PathBuf, Error> {
+    let dirs = library_dirs();
+    if dirs.is_empty() {
+        bail!("no library directories are configured");
+    }
+    let candidates: Vec<PathBuf> = if path.is_absolute() {
+        vec![path.to_owned()]
+    } else {
+        dirs.iter().map(|dir| dir.join(path)).collect()
+    };
+    candidates
+        .into_iter()
+        .filter_map(|path| path.canonicalize().ok())
+        .find(|path| dirs.iter().any(|dir| path.starts_with(dir)) && path.is_file())
+        .ok_or_else(|| eyre!("{} is not in the library", path.display()))
+}
+
+/// Tags read from an audio file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub track_number: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl Tags {
+    fn read_revision(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            if tag.std_key == Some(StandardTagKey::TrackNumber) {
+                // sometimes written as "3/12"
+                let value = tag.value.to_string();
+                let number = value.split('/').next().unwrap_or_default().trim().parse();
+                self.track_number = self.track_number.or(number.ok());
+                continue;
+            }
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                Some(StandardTagKey::Genre) => &mut self.genre,
+                Some(StandardTagKey::Date) => &mut self.date,
+                _ => continue,
+            };
+            field.get_or_insert_with(|| tag.value.to_string());
+        }
+    }
+
+    /// Untagged files are titled after `file_name`.
+    pub fn into_metadata(self, file_name: &Path, source_url: String) -> AuxMetadata {
+        AuxMetadata {
+            title: self.title.or_else(|| {
+                file_name
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+            }),
+            artist: self.artist,
+            album: self.album,
+            date: self.date,
+            duration: self.duration,
+            source_url: Some(source_url),
+            ..AuxMetadata::default()
+        }
+    }
+}
+
+/// Reads the tags and duration of the audio file at `path`. This blocks.
+pub fn read_tags(path: &Path) -> Result<Tags, Error> {
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+    probe_tags(Box::new(File::open(path)?), &hint)
+}
+
+/// Detects the format of `source` and reads its tags and duration.
+pub fn probe_tags(source: Box<dyn MediaSource>, hint: &Hint) -> Result<Tags, Error> {
+    let source = MediaSourceStream::new(source, Default::default());
+    let mut probed = symphonia::default::get_probe().format(
+        hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut tags = Tags::default();
+    // tags inside the container take precedence over ones before it, like ID3v2
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.read_revision(revision);
+    }
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.read_revision(revision);
+    }
+    if let Some(track) = probed.format.default_track() {
+        let params = &track.codec_params;
+        if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+            let time = time_base.calc_time(frames);
+            tags.duration =
+                Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac));
+        }
+    }
+    Ok(tags)
+}
+
+/// A file from the library, played through songbird's own file source.
+pub struct LocalFile {
+    path: PathBuf,
+}
+
+impl LocalFile {
+    pub fn new(path: PathBuf) -> Self {
+        LocalFile { path }
+    }
+
+    async fn resolve(&self) -> Result<PathBuf, AudioStreamError> {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || resolve(&path))
+            .await
+            .unwrap()
+            .map_err(|e| AudioStreamError::Fail(e.into()))
+    }
+}
+
+impl From<LocalFile> for Input {
+    fn from(file: LocalFile) -> Self {
+        Input::Lazy(Box::new(file))
+    }
+}
+
+#[async_trait]
+impl Compose for LocalFile {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Fail(
+            "files should be opened asynchronously".into(),
+        ))
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let path = self.resolve().await?;
+        songbird::input::File::new(path).create_async().await
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        let path = self.resolve().await?;
+        tokio::task::spawn_blocking(move || {
+            read_tags(&path).map(|tags| tags.into_metadata(&path, path.display().to_string()))
+        })
+        .await
+        .unwrap()
+        .map_err(|e| AudioStreamError::Fail(e.into()))
+    }
+}
+
+#[poise::command(slash_command, category = "Enqueue")]
+/// Add a file from the music library to the queue.
+pub async fn playfile(
+    ctx: Context<'_>,
+    #[description = "path of the file, relative to a library directory"] path: String,
+) -> CommandResult {
+    play_common(
+        ctx,
+        path,
+        |_, path| Queueable::File { path: path.into() },
+        false,
+    )
+    .await
+}
@@ -0,0 +1,357 @@
+use std::net::SocketAddr;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response as HttpResponse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use poise::futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use songbird::error::ControlError;
+use songbird::id::GuildId;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::info;
+
+use crate::auth::{required_scope, Scope, Token};
+use crate::dashboard::{self, Dashboard};
+use crate::extractor::ExtractorError;
+use crate::overlay;
+use crate::play::Queueable;
+use crate::queue::QueueError;
+use crate::ws::{bearer_token, ClientMessage, Remote, RemoteError, Response, ServerMessage};
+
+/// Serves a REST API over the same [`Remote`] the WebSocket server uses,
+/// along with the web dashboard.
+pub struct HttpServer {
+    remote: Arc<Remote>,
+    dashboard: Arc<Dashboard>,
+}
+
+impl HttpServer {
+    pub fn new(remote: Arc<Remote>, dashboard: Arc<Dashboard>) -> Self {
+        Self { remote, dashboard }
+    }
+
+    pub async fn listen(self, addr: SocketAddr) -> color_eyre::Result<()> {
+        let app = Router::new()
+            .route("/guilds/{guild}/queue", get(queue))
+            .route("/guilds/{guild}/events", get(events))
+            .route("/guilds/{guild}/search", post(search))
+            .route("/guilds/{guild}/enqueue", post(enqueue))
+            .route("/guilds/{guild}/skip", post(skip))
+            .route("/guilds/{guild}/pause", post(pause))
+            .route("/guilds/{guild}/resume", post(resume))
+            .route("/guilds/{guild}/clear", post(clear))
+            .route("/guilds/{guild}/shuffle", post(shuffle))
+            .route("/guilds/{guild}/loop", post(toggle_loop))
+            .route("/guilds/{guild}/seek", post(seek))
+            .route("/guilds/{guild}/move", post(r#move))
+            .route("/guilds/{guild}/swap", post(swap))
+            .route("/guilds/{guild}/remove", post(remove))
+            .route("/guilds/{guild}/join", post(join))
+            .route("/guilds/{guild}/leave", post(leave))
+            .merge(overlay::router())
+            .with_state(self.remote)
+            .merge(dashboard::router(self.dashboard));
+        let listener = TcpListener::bind(addr).await?;
+        info!(%addr, "http server listening");
+        axum::serve(listener, app).await?;
+        Ok(())
+    }
+}
+
+pub enum ApiError {
+    Unauthorized,
+    Forbidden(String),
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    /// yt-dlp or a site it asked failed.
+    BadGateway(String),
+    Internal(String),
+}
+
+impl ApiError {
+    /// Picks the status for an error from [`Remote::handle`] by what failed.
+    fn from_remote(e: color_eyre::Report) -> Self {
+        let message = e.to_string();
+        if let Some(e) = e.downcast_ref::<RemoteError>() {
+            match e {
+                RemoteError::Invalid(_) => ApiError::BadRequest(message),
+                RemoteError::Conflict(_) => ApiError::Conflict(message),
+            }
+        } else if let Some(e) = e.downcast_ref::<QueueError>() {
+            match e {
+                QueueError::NoTrack(_) => ApiError::NotFound(message),
+                QueueError::Current(_) => ApiError::Conflict(message),
+                QueueError::Stop(_) => ApiError::Internal(message),
+            }
+        } else if let Some(e) = e.downcast_ref::<ExtractorError>() {
+            match e {
+                ExtractorError::NoResults => ApiError::NotFound(message),
+                ExtractorError::NotInstalled(_) => ApiError::Internal(message),
+                _ => ApiError::BadGateway(message),
+            }
+        } else if e.downcast_ref::<ControlError>().is_some() {
+            // the track ended or its call was left meanwhile
+            ApiError::Conflict(message)
+        } else {
+            ApiError::Internal(message)
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> HttpResponse {
+        let (status, error) = match self {
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "missing or invalid token".into()),
+            ApiError::Forbidden(e) => (StatusCode::FORBIDDEN, e),
+            ApiError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+            ApiError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            ApiError::Conflict(e) => (StatusCode::CONFLICT, e),
+            ApiError::BadGateway(e) => (StatusCode::BAD_GATEWAY, e),
+            ApiError::Internal(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        };
+        (status, Json(json!({ "error": error }))).into_response()
+    }
+}
+
+/// The token the request was made with.
+pub struct Authorized(pub Token);
+
+impl FromRequestParts<Arc<Remote>> for Authorized {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        remote: &Arc<Remote>,
+    ) -> Result<Self, Self::Rejection> {
+        bearer_token(&parts.headers, &parts.uri)
+            .and_then(|secret| remote.tokens.get(&secret))
+            .map(Authorized)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+/// Runs `message` the same way a WebSocket client sending it would.
+pub async fn run(
+    remote: &Remote,
+    token: &Token,
+    guild: NonZeroU64,
+    message: ClientMessage,
+) -> Result<Response, ApiError> {
+    let guild = GuildId(guild);
+    token
+        .authorize(guild, required_scope(&message))
+        .map_err(|e| ApiError::Forbidden(e.to_string()))?;
+    remote
+        .handle(token, guild, message)
+        .await
+        .map_err(ApiError::from_remote)
+}
+
+/// Events in `guild`, ending once `token` is revoked or expires.
+pub fn event_stream(
+    remote: Arc<Remote>,
+    token: Token,
+    guild: GuildId,
+) -> impl Stream<Item = ServerMessage> {
+    let receiver = remote.events.subscribe();
+    stream::unfold(receiver, move |mut receiver| {
+        let remote = remote.clone();
+        let token = token.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((g, message)) if g == guild => {
+                        remote.tokens.current(&token)?;
+                        return Some((message, receiver));
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
+
+pub type Guild = Path<NonZeroU64>;
+
+async fn events(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let guild = GuildId(guild);
+    token
+        .authorize(guild, Scope::Read)
+        .map_err(|e| ApiError::Forbidden(e.to_string()))?;
+    let events =
+        event_stream(remote, token, guild).map(|message| Event::default().json_data(message));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn queue(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+) -> Result<HttpResponse, ApiError> {
+    match run(&remote, &token, guild, ClientMessage::QueueSnapshot).await? {
+        Response::Queue(snapshot) => Ok(Json(snapshot).into_response()),
+        _ => unreachable!("QueueSnapshot is always answered with a queue"),
+    }
+}
+
+async fn command(
+    remote: &Remote,
+    token: &Token,
+    guild: NonZeroU64,
+    message: ClientMessage,
+) -> Result<StatusCode, ApiError> {
+    run(remote, token, guild, message).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+macro_rules! simple_commands {
+    ($($name:ident => $message:ident),*$(,)?) => {
+        $(
+            async fn $name(
+                State(remote): State<Arc<Remote>>,
+                Authorized(token): Authorized,
+                Path(guild): Guild,
+            ) -> Result<StatusCode, ApiError> {
+                command(&remote, &token, guild, ClientMessage::$message).await
+            }
+        )*
+    };
+}
+
+simple_commands! {
+    skip => Skip,
+    pause => Pause,
+    resume => Resume,
+    clear => ClearAll,
+    shuffle => Shuffle,
+    toggle_loop => ToggleLoop,
+    leave => Leave,
+}
+
+async fn enqueue(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(q): Json<Queueable>,
+) -> Result<StatusCode, ApiError> {
+    command(&remote, &token, guild, ClientMessage::Enqueue(q)).await
+}
+
+#[derive(Deserialize)]
+struct Search {
+    keyword: String,
+    #[serde(default = "default_results")]
+    num: usize,
+}
+
+fn default_results() -> usize {
+    5
+}
+
+async fn search(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Search { keyword, num }): Json<Search>,
+) -> Result<HttpResponse, ApiError> {
+    match run(
+        &remote,
+        &token,
+        guild,
+        ClientMessage::Search { keyword, num },
+    )
+    .await?
+    {
+        Response::SearchResults(results) => Ok(Json(results).into_response()),
+        _ => unreachable!("Search is always answered with results"),
+    }
+}
+
+#[derive(Deserialize)]
+struct Seek {
+    seconds: f64,
+}
+
+async fn seek(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Seek { seconds }): Json<Seek>,
+) -> Result<StatusCode, ApiError> {
+    let position =
+        Duration::try_from_secs_f64(seconds).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    command(&remote, &token, guild, ClientMessage::Seek(position)).await
+}
+
+#[derive(Deserialize)]
+struct Move {
+    from: usize,
+    to: usize,
+}
+
+async fn r#move(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Move { from, to }): Json<Move>,
+) -> Result<StatusCode, ApiError> {
+    command(&remote, &token, guild, ClientMessage::Move { from, to }).await
+}
+
+#[derive(Deserialize)]
+struct Swap {
+    a: usize,
+    b: usize,
+}
+
+async fn swap(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Swap { a, b }): Json<Swap>,
+) -> Result<StatusCode, ApiError> {
+    command(&remote, &token, guild, ClientMessage::Swap { a, b }).await
+}
+
+#[derive(Deserialize)]
+struct Remove {
+    index: usize,
+}
+
+async fn remove(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Remove { index }): Json<Remove>,
+) -> Result<StatusCode, ApiError> {
+    command(&remote, &token, guild, ClientMessage::Remove(index)).await
+}
+
+#[derive(Deserialize)]
+struct Join {
+    channel: NonZeroU64,
+}
+
+async fn join(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+    Json(Join { channel }): Json<Join>,
+) -> Result<StatusCode, ApiError> {
+    command(&remote, &token, guild, ClientMessage::Join { channel }).await
+}
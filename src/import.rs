@@ -0,0 +1,505 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{bail, eyre};
+use poise::serenity_prelude::Attachment;
+use poise::CreateReply;
+use serde_json::Value;
+
+use crate::play::{enqueue, Queueable};
+use crate::resolve::best_match;
+use crate::search::{default_provider, Provider};
+use crate::vc::enter_vc;
+use crate::ws::ServerMessage;
+use crate::{CommandResult, Context, Data, Error};
+
+crate::commands!(import);
+
+/// Longer playlists are cut off, since every song is a yt-dlp search.
+const MAX_ENTRIES: usize = 500;
+
+/// Discord doesn't allow longer messages.
+const MAX_MESSAGE_LEN: usize = 2000;
+
+/// A song listed in an export file.
+struct Entry {
+    artist: Option<String>,
+    title: String,
+    /// YouTube Music exports have the video instead of a title.
+    url: Option<String>,
+    /// Helps tell the song apart from other versions of it.
+    duration: Option<Duration>,
+}
+
+impl Entry {
+    fn new(artist: Option<&str>, title: &str) -> Self {
+        Entry {
+            artist: artist
+                .map(|a| a.trim().replace(';', ", "))
+                .filter(|a| !a.is_empty()),
+            title: title.trim().to_owned(),
+            url: None,
+            duration: None,
+        }
+    }
+
+    fn with_duration_ms(mut self, ms: Option<u64>) -> Self {
+        self.duration = ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+        self
+    }
+
+    fn describe(&self) -> String {
+        match (&self.artist, &self.url) {
+            (_, Some(url)) => url.clone(),
+            (Some(artist), None) => format!("{artist} - {}", self.title),
+            (None, None) => self.title.clone(),
+        }
+    }
+
+    /// Searches for `artist - title` like `/splay` does.
+    async fn resolve(&self, provider: Provider, data: &Data) -> Result<Option<Queueable>, Error> {
+        if let Some(url) = &self.url {
+            return Ok(Some(Queueable::Ytdl { arg: url.clone() }));
+        }
+        best_match(provider, data, &self.describe(), self.duration).await
+    }
+}
+
+struct Playlist {
+    name: Option<String>,
+    entries: Vec<Entry>,
+}
+
+impl Playlist {
+    fn unnamed(entries: Vec<Entry>) -> Vec<Playlist> {
+        vec![Playlist {
+            name: None,
+            entries,
+        }]
+    }
+}
+
+/// Finds the column named like one of `names`.
+fn column(headers: &[String], names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .find_map(|name| headers.iter().position(|h| h == name))
+}
+
+/// Reads CSV exports, like Exportify's for Spotify and Google Takeout's for YouTube Music.
+fn parse_csv(bytes: &[u8]) -> Result<Vec<Playlist>, Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(bytes);
+    let headers = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    let title = column(
+        &headers,
+        &["track name", "song title", "title", "name", "track", "song"],
+    );
+    let artist = column(
+        &headers,
+        &[
+            "artist name(s)",
+            "artist names",
+            "artist name",
+            "artists",
+            "artist",
+        ],
+    );
+    let video = column(&headers, &["video id"]);
+    let duration = column(&headers, &["duration (ms)", "track duration (ms)"]);
+    if title.is_none() && video.is_none() {
+        bail!("could not find a title column");
+    }
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).filter(|f| !f.is_empty());
+        if let Some(id) = field(video) {
+            entries.push(Entry {
+                artist: None,
+                title: String::new(),
+                url: Some(format!("https://music.youtube.com/watch?v={}", id.trim())),
+                duration: None,
+            });
+        } else if let Some(title) = field(title) {
+            let ms = field(duration).and_then(|ms| ms.trim().parse().ok());
+            entries.push(Entry::new(field(artist), title).with_duration_ms(ms));
+        }
+    }
+    Ok(Playlist::unnamed(entries))
+}
+
+/// Picks up objects that look like songs anywhere in a JSON document.
+fn collect_json(value: &Value, entries: &mut Vec<Entry>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| collect_json(item, entries)),
+        Value::Object(object) => {
+            let str_field = |key| object.get(key).and_then(Value::as_str);
+            // Spotify uses `trackName`/`artistName` in playlists and history,
+            // and `track`/`artist` in the saved library
+            if let Some(title) = str_field("trackName").or_else(|| str_field("track")) {
+                let artist = str_field("artistName").or_else(|| str_field("artist"));
+                entries.push(Entry::new(artist, title));
+            } else {
+                object.values().for_each(|v| collect_json(v, entries));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads JSON exports, like the playlists in Spotify's account data download.
+fn parse_json(bytes: &[u8]) -> Result<Vec<Playlist>, Error> {
+    let value = serde_json::from_slice::<Value>(bytes)?;
+    if let Some(playlists) = value.get("playlists").and_then(Value::as_array) {
+        return Ok(playlists
+            .iter()
+            .map(|playlist| {
+                let mut entries = Vec::new();
+                collect_json(&playlist["items"], &mut entries);
+                Playlist {
+                    name: playlist["name"].as_str().map(str::to_owned),
+                    entries,
+                }
+            })
+            .collect());
+    }
+    let mut entries = Vec::new();
+    collect_json(&value, &mut entries);
+    Ok(Playlist::unnamed(entries))
+}
+
+/// Reads the XML that Apple Music exports libraries and playlists as.
+fn parse_plist(bytes: &[u8]) -> Result<Vec<Playlist>, Error> {
+    let value = plist::Value::from_reader(Cursor::new(bytes))?;
+    let root = value
+        .as_dictionary()
+        .ok_or_else(|| eyre!("not an Apple Music export"))?;
+    let tracks = root
+        .get("Tracks")
+        .and_then(plist::Value::as_dictionary)
+        .ok_or_else(|| eyre!("not an Apple Music export"))?;
+    let entry = |track: &plist::Value| {
+        let track = track.as_dictionary()?;
+        let str_field = |key| track.get(key).and_then(plist::Value::as_string);
+        let ms = track
+            .get("Total Time")
+            .and_then(plist::Value::as_unsigned_integer);
+        Some(Entry::new(str_field("Artist"), str_field("Name")?).with_duration_ms(ms))
+    };
+    let Some(playlists) = root.get("Playlists").and_then(plist::Value::as_array) else {
+        return Ok(Playlist::unnamed(
+            tracks.values().filter_map(entry).collect(),
+        ));
+    };
+    Ok(playlists
+        .iter()
+        .filter_map(plist::Value::as_dictionary)
+        .map(|playlist| {
+            let items = playlist
+                .get("Playlist Items")
+                .and_then(plist::Value::as_array)
+                .map_or(&[][..], Vec::as_slice);
+            let entries = items
+                .iter()
+                .filter_map(|item| {
+                    let id = item.as_dictionary()?.get("Track ID")?.as_signed_integer()?;
+                    entry(tracks.get(&id.to_string())?)
+                })
+                .collect();
+            Playlist {
+                name: playlist
+                    .get("Name")
+                    .and_then(plist::Value::as_string)
+                    .map(str::to_owned),
+                entries,
+            }
+        })
+        .collect())
+}
+
+/// Parses an export file by its extension, or by its first character if it has none.
+fn parse_export(filename: &str, bytes: &[u8]) -> Result<Vec<Playlist>, Error> {
+    let ext = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    let first = bytes
+        .iter()
+        .find(|b| !b.is_ascii_whitespace())
+        .copied()
+        .unwrap_or_default();
+    match (ext.as_deref(), first) {
+        (Some("csv"), _) => parse_csv(bytes),
+        (Some("json"), _) | (_, b'{' | b'[') => parse_json(bytes),
+        (Some("xml" | "plist"), _) | (_, b'<') => parse_plist(bytes),
+        _ => parse_csv(bytes),
+    }
+}
+
+/// Appends as many lines as fit in a Discord message.
+fn summarize(mut msg: String, failed: &[String]) -> String {
+    if failed.is_empty() {
+        return msg;
+    }
+    msg.push_str("\nCould not find:");
+    for (i, line) in failed.iter().enumerate() {
+        let more = format!("\n...and {} more", failed.len() - i);
+        if msg.len() + line.len() + 3 + more.len() > MAX_MESSAGE_LEN {
+            msg.push_str(&more);
+            break;
+        }
+        msg.push_str("\n- ");
+        msg.push_str(line);
+    }
+    msg
+}
+
+#[poise::command(slash_command, guild_only, category = "Enqueue")]
+/// Import a playlist exported from Spotify, Apple Music or YouTube Music
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "CSV, JSON or XML export file"] file: Attachment,
+    #[description = "which playlist to import, for files with several"] playlist: Option<String>,
+) -> CommandResult {
+    ctx.defer().await?;
+    let bytes = file.download().await?;
+    let playlists = match parse_export(&file.filename, &bytes) {
+        Ok(playlists) => playlists,
+        Err(e) => {
+            ctx.say(format!("Could not read {}: {e}", file.filename))
+                .await?;
+            return Ok(());
+        }
+    };
+    let found = match &playlist {
+        Some(name) => playlists.into_iter().find(|p| {
+            p.name
+                .as_deref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        }),
+        None if playlists.len() == 1 => playlists.into_iter().next(),
+        None => {
+            let names = playlists
+                .iter()
+                .filter_map(|p| p.name.as_deref())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let msg = format!(
+                "This file has {} playlists, pick one with the playlist option: {names}",
+                playlists.len()
+            );
+            ctx.say(msg.chars().take(MAX_MESSAGE_LEN).collect::<String>())
+                .await?;
+            return Ok(());
+        }
+    };
+    let Some(Playlist { name, mut entries }) = found else {
+        ctx.say("No playlist with that name in the file").await?;
+        return Ok(());
+    };
+    if entries.is_empty() {
+        ctx.say("No songs found in the file").await?;
+        return Ok(());
+    }
+    let total = entries.len();
+    entries.truncate(MAX_ENTRIES);
+    let name = name.unwrap_or(file.filename);
+    let provider = default_provider(ctx);
+
+    enter_vc(ctx, true, |handler, ctx| async move {
+        let status = ctx
+            .say(format!("Importing {} songs from {name}...", entries.len()))
+            .await?;
+        let mut queued = 0;
+        let mut failed = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let enqueued = match entry.resolve(provider, ctx.data()).await {
+                Ok(Some(q)) => enqueue(ctx, ctx.guild_id().unwrap().into(), q, &handler)
+                    .await
+                    .is_ok(),
+                _ => false,
+            };
+            if enqueued {
+                queued += 1;
+                ctx.data()
+                    .events
+                    .emit(ctx.guild_id().unwrap(), ServerMessage::QueueChanged);
+            } else {
+                failed.push(entry.describe());
+            }
+            if (i + 1) % 10 == 0 {
+                let msg = format!("Importing {name}: {}/{}", i + 1, entries.len());
+                status
+                    .edit(ctx, CreateReply::default().content(msg))
+                    .await?;
+            }
+        }
+        let mut msg = format!("Queued {queued} of {} songs from {name}", entries.len());
+        if total > MAX_ENTRIES {
+            msg.push_str(&format!(
+                " (only the first {MAX_ENTRIES} of {total} are imported)"
+            ));
+        }
+        let msg = summarize(msg, &failed);
+        status
+            .edit(ctx, CreateReply::default().content(msg))
+            .await?;
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_csv, parse_export, parse_json, parse_plist, Playlist};
+
+    /// Each playlist's name and its songs as `/import` would search for them.
+    fn songs(playlists: Vec<Playlist>) -> Vec<(Option<String>, Vec<String>)> {
+        playlists
+            .into_iter()
+            .map(|p| (p.name, p.entries.iter().map(|e| e.describe()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_exportify_csv() {
+        let csv = "\
+Track URI,Track Name,Artist Name(s),Album Name,Track Duration (ms)
+spotify:track:1,Song One,Artist A;Artist B,Album,201000
+spotify:track:2, Song Two ,Artist C,Album,0
+spotify:track:3,,Artist D,Album,1000
+";
+        let playlists = parse_csv(csv.as_bytes()).unwrap();
+        let entries = &playlists[0].entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].describe(), "Artist A, Artist B - Song One");
+        assert_eq!(entries[0].duration, Some(Duration::from_millis(201000)));
+        assert_eq!(entries[1].describe(), "Artist C - Song Two");
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn reads_takeout_csv() {
+        let csv = "\
+Video ID,Time Added
+ abc123 ,2024-01-01
+,2024-01-02
+";
+        assert_eq!(
+            songs(parse_csv(csv.as_bytes()).unwrap()),
+            [(
+                None,
+                vec!["https://music.youtube.com/watch?v=abc123".into()]
+            )]
+        );
+
+        let csv = "Song Title,Artists\nSong,Artist\n";
+        assert_eq!(
+            songs(parse_csv(csv.as_bytes()).unwrap()),
+            [(None, vec!["Artist - Song".into()])]
+        );
+        assert!(parse_csv(b"Album,Year\nAlbum,2024\n").is_err());
+    }
+
+    #[test]
+    fn reads_spotify_json() {
+        let json = r#"{"playlists": [
+            {"name": "Mix", "items": [
+                {"track": {"trackName": "Song", "artistName": "Artist"}},
+                {"episode": null}
+            ]},
+            {"name": "Empty", "items": []}
+        ]}"#;
+        assert_eq!(
+            songs(parse_json(json.as_bytes()).unwrap()),
+            [
+                (Some("Mix".into()), vec!["Artist - Song".into()]),
+                (Some("Empty".into()), vec![]),
+            ]
+        );
+
+        let json = r#"[{"artist": "Artist", "track": "Song"}, {"album": "Album"}]"#;
+        assert_eq!(
+            songs(parse_json(json.as_bytes()).unwrap()),
+            [(None, vec!["Artist - Song".into()])]
+        );
+    }
+
+    const PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Tracks</key>
+    <dict>
+        <key>1</key>
+        <dict>
+            <key>Name</key><string>Song One</string>
+            <key>Artist</key><string>Artist</string>
+            <key>Total Time</key><integer>180000</integer>
+        </dict>
+        <key>2</key>
+        <dict>
+            <key>Name</key><string>Song Two</string>
+        </dict>
+    </dict>
+    <key>Playlists</key>
+    <array>
+        <dict>
+            <key>Name</key><string>Favourites</string>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>2</integer></dict>
+                <dict><key>Track ID</key><integer>3</integer></dict>
+                <dict><key>Track ID</key><integer>1</integer></dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn reads_apple_music_plist() {
+        let playlists = parse_plist(PLIST.as_bytes()).unwrap();
+        assert_eq!(
+            playlists[0].entries[1].duration,
+            Some(Duration::from_secs(180))
+        );
+        assert_eq!(
+            songs(playlists),
+            [(
+                Some("Favourites".into()),
+                vec!["Song Two".into(), "Artist - Song One".into()]
+            )]
+        );
+        assert!(parse_plist(b"<plist version=\"1.0\"><array/></plist>").is_err());
+    }
+
+    #[test]
+    fn detects_the_format() {
+        let json = br#"  {"track": "Song", "artist": "Artist"}"#;
+        assert_eq!(
+            songs(parse_export("export", json).unwrap()),
+            [(None, vec!["Artist - Song".into()])]
+        );
+        assert_eq!(
+            songs(parse_export("Library", PLIST.as_bytes()).unwrap())[0]
+                .1
+                .len(),
+            2
+        );
+        assert_eq!(
+            songs(parse_export("export.txt", b"Title,Artist\nSong,Artist\n").unwrap()),
+            [(None, vec!["Artist - Song".into()])]
+        );
+        // the extension wins over the first byte
+        assert!(parse_export("export.csv", json).is_err());
+    }
+}
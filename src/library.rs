@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use poise::serenity_prelude::CreateEmbed;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info, warn};
+
+use crate::file::{library_dirs, read_tags, Tags};
+use crate::metadata::format_duration;
+use crate::play::{play_multiple, Queueable};
+use crate::search::offer_choices;
+use crate::vc::enter_vc;
+use crate::{CommandResult, Context};
+
+crate::commands!(library);
+
+/// Files with these extensions are indexed.
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "alac", "flac", "ogg", "oga", "wav"];
+
+/// How often the library directories are rescanned.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// Modification time in seconds, so unchanged files aren't read again.
+    modified: u64,
+    #[serde(flatten)]
+    pub tags: Tags,
+}
+
+impl Entry {
+    pub fn title(&self) -> String {
+        self.tags.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_stem()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+        })
+    }
+
+    fn queueable(&self) -> Queueable {
+        Queueable::File {
+            path: self.path.clone(),
+        }
+    }
+
+    /// Whether every word of the query appears in the artist, album, title or genre.
+    fn matches(&self, words: &[String]) -> bool {
+        let Tags {
+            artist,
+            album,
+            genre,
+            ..
+        } = &self.tags;
+        let haystack = [
+            Some(self.title()),
+            artist.clone(),
+            album.clone(),
+            genre.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase();
+        words.iter().all(|word| haystack.contains(word))
+    }
+}
+
+fn words(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_lowercase).collect()
+}
+
+/// An index of the tags of every file in the library directories, saved as JSON.
+pub struct Library {
+    path: PathBuf,
+    entries: RwLock<Vec<Entry>>,
+}
+
+impl Library {
+    /// Loads the index from `LIBRARY_INDEX_PATH`, defaulting to `./library.json`.
+    pub async fn load() -> color_eyre::Result<Arc<Self>> {
+        let path = PathBuf::from(env::var("LIBRARY_INDEX_PATH").unwrap_or("./library.json".into()));
+        let entries = match fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Arc::new(Library {
+            path,
+            entries: RwLock::new(entries),
+        }))
+    }
+
+    /// A library holding `entries` that is never saved.
+    #[cfg(test)]
+    pub(crate) fn with_entries(entries: Vec<Entry>) -> Self {
+        Library {
+            path: PathBuf::new(),
+            entries: RwLock::new(entries),
+        }
+    }
+
+    /// Rescans the library directories every [`REFRESH_INTERVAL`].
+    pub fn spawn_refresh(self: Arc<Self>) {
+        if library_dirs().is_empty() {
+            return;
+        }
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.refresh().await {
+                    warn!("failed to refresh the library: {e}");
+                }
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+            }
+        });
+    }
+
+    /// Updates the index, only reading tags of files that are new or have changed.
+    pub async fn refresh(&self) -> color_eyre::Result<()> {
+        let known = self
+            .entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.clone()))
+            .collect();
+        let entries = tokio::task::spawn_blocking(move || scan(known)).await?;
+        info!("indexed {} files in the library", entries.len());
+        let s = serde_json::to_string(&entries)?;
+        *self.entries.write().unwrap() = entries;
+        fs::write(&self.path, s).await?;
+        Ok(())
+    }
+
+    pub fn search(&self, query: &str) -> Vec<Entry> {
+        let words = words(query);
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.matches(&words))
+            .cloned()
+            .collect()
+    }
+
+    /// Albums whose name contains `query`, with their tracks in order.
+    ///
+    /// Tracks are grouped by album name and directory rather than artist, so
+    /// compilations stay together while same-named albums don't merge.
+    pub fn albums(&self, query: &str) -> Vec<(String, Vec<Entry>)> {
+        let words = words(query);
+        // album name, the directory it is in, and its tracks
+        let mut albums: Vec<(&str, Option<&Path>, Vec<Entry>)> = Vec::new();
+        let entries = self.entries.read().unwrap();
+        for entry in entries.iter() {
+            let Some(album) = &entry.tags.album else {
+                continue;
+            };
+            let name = album.to_lowercase();
+            if !words.iter().all(|word| name.contains(word)) {
+                continue;
+            }
+            let dir = entry.path.parent();
+            match albums.iter_mut().find(|(a, d, _)| a == album && *d == dir) {
+                Some((_, _, tracks)) => tracks.push(entry.clone()),
+                None => albums.push((album, dir, vec![entry.clone()])),
+            }
+        }
+        albums
+            .into_iter()
+            .map(|(album, _, mut tracks)| {
+                tracks.sort_by(|a, b| {
+                    a.tags
+                        .track_number
+                        .cmp(&b.tags.track_number)
+                        .then_with(|| a.path.cmp(&b.path))
+                });
+                (album.to_owned(), tracks)
+            })
+            .collect()
+    }
+}
+
+fn scan(mut known: HashMap<PathBuf, Entry>) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut dirs = library_dirs().to_vec();
+    while let Some(dir) = dirs.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            warn!("cannot read library directory {}", dir.display());
+            continue;
+        };
+        for item in read_dir.flatten() {
+            let path = item.path();
+            let Ok(meta) = item.metadata() else { continue };
+            if meta.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !is_audio(&path) {
+                continue;
+            }
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |time| time.as_secs());
+            match known.remove(&path) {
+                Some(entry) if entry.modified == modified => entries.push(entry),
+                _ => match read_tags(&path) {
+                    Ok(tags) => entries.push(Entry {
+                        path,
+                        modified,
+                        tags,
+                    }),
+                    Err(e) => debug!("skipping {}: {e}", path.display()),
+                },
+            }
+        }
+    }
+    entries.sort_by(|a, b| {
+        let key = |e: &Entry| {
+            (
+                e.tags.artist.clone(),
+                e.tags.album.clone(),
+                e.tags.track_number,
+            )
+        };
+        key(a).cmp(&key(b)).then_with(|| a.path.cmp(&b.path))
+    });
+    entries
+}
+
+pub(crate) fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Library",
+    subcommands("library_search", "library_play", "album")
+)]
+/// Browse the local music library
+pub async fn library(_ctx: Context<'_>) -> CommandResult {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only, rename = "search")]
+/// Search the library by artist, album, title and genre
+async fn library_search(
+    ctx: Context<'_>,
+    #[description = "words to search for"] query: String,
+    #[description = "number of results to display"] num: Option<usize>,
+) -> CommandResult {
+    if num.is_some_and(|x| x > 25) {
+        ctx.say("Number of results must be less than 25.").await?;
+        return Ok(());
+    }
+    let mut results = ctx.data().library.search(&query);
+    if results.is_empty() {
+        ctx.say(format!("Nothing in the library matches \"{query}\""))
+            .await?;
+        return Ok(());
+    }
+    results.truncate(num.unwrap_or(5));
+
+    let mut embed = CreateEmbed::default().title(format!("Library results for \"{query}\""));
+    for (i, entry) in results.iter().enumerate() {
+        let Tags {
+            artist,
+            album,
+            duration,
+            ..
+        } = &entry.tags;
+        embed = embed.field(
+            format!("{}: {}", i + 1, entry.title()),
+            format!(
+                "{} - {} ({})",
+                artist.as_deref().unwrap_or("unknown artist"),
+                album.as_deref().unwrap_or("unknown album"),
+                duration.map_or("unknown".into(), format_duration)
+            ),
+            false,
+        );
+    }
+    let choices = results
+        .iter()
+        .map(|entry| (entry.title(), vec![entry.queueable()]))
+        .collect();
+    offer_choices(ctx, embed, choices).await
+}
+
+#[poise::command(slash_command, guild_only, rename = "play")]
+/// Play the best match from the library
+async fn library_play(
+    ctx: Context<'_>,
+    #[description = "words to search for"] query: String,
+) -> CommandResult {
+    let Some(entry) = ctx.data().library.search(&query).into_iter().next() else {
+        ctx.say(format!("Nothing in the library matches \"{query}\""))
+            .await?;
+        return Ok(());
+    };
+    ctx.defer().await?;
+    enter_vc(ctx, true, |handler, ctx| async move {
+        play_multiple(ctx, vec![entry.queueable()], &handler).await
+    })
+    .await
+}
+
+#[poise::command(slash_command, guild_only)]
+/// Find albums in the library and play them
+async fn album(
+    ctx: Context<'_>,
+    #[description = "name of the album"] name: String,
+) -> CommandResult {
+    let mut albums = ctx.data().library.albums(&name);
+    if albums.is_empty() {
+        ctx.say(format!("No album in the library matches \"{name}\""))
+            .await?;
+        return Ok(());
+    }
+    albums.truncate(25);
+
+    let mut embed = CreateEmbed::default().title(format!("Albums matching \"{name}\""));
+    for (i, (album, tracks)) in albums.iter().enumerate() {
+        let artist = match tracks[0].tags.artist.as_deref() {
+            _ if tracks
+                .iter()
+                .any(|t| t.tags.artist != tracks[0].tags.artist) =>
+            {
+                "various artists"
+            }
+            Some(artist) => artist,
+            None => "unknown artist",
+        };
+        embed = embed.field(
+            format!("{}: {album}", i + 1),
+            format!("{artist}, {} tracks", tracks.len()),
+            false,
+        );
+    }
+    let choices = albums
+        .into_iter()
+        .map(|(album, tracks)| (album, tracks.iter().map(Entry::queueable).collect()))
+        .collect();
+    offer_choices(ctx, embed, choices).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, artist: &str, album: &str, track_number: u32) -> Entry {
+        Entry {
+            path: path.into(),
+            modified: 0,
+            tags: Tags {
+                artist: Some(artist.into()),
+                album: Some(album.into()),
+                track_number: Some(track_number),
+                ..Tags::default()
+            },
+        }
+    }
+
+    #[test]
+    fn groups_albums_by_directory() {
+        let mut entries = vec![
+            entry("/mix/2.mp3", "A", "Hits", 2),
+            entry("/b/hits/1.mp3", "B", "Hits", 1),
+            entry("/mix/1.mp3", "B", "Hits", 1),
+        ];
+        entries.sort_by(|a, b| a.tags.artist.cmp(&b.tags.artist));
+        let library = Library::with_entries(entries);
+        let albums = library.albums("hits");
+        let paths = albums
+            .iter()
+            .map(|(_, tracks)| tracks.iter().map(|t| t.path.to_str().unwrap()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            paths,
+            [vec!["/mix/1.mp3", "/mix/2.mp3"], vec!["/b/hits/1.mp3"]]
+        );
+    }
+}
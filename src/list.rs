@@ -9,6 +9,7 @@ use poise::serenity_prelude::{
 };
 use poise::CreateReply;
 use serenity::prelude::Mutex;
+use songbird::tracks::PlayMode;
 use songbird::Call;
 use tokio::spawn;
 use tokio::time::timeout;
@@ -17,6 +18,7 @@ use tracing::error;
 use crate::metadata::{format_duration, format_metadata};
 use crate::track::TrackData;
 use crate::vc::enter_vc;
+use crate::ws::{QueueEntry, QueueSnapshot};
 use crate::{CommandResult, Context, DiscordContext, Error};
 
 crate::commands!(queue);
@@ -44,7 +46,7 @@ pub async fn retrieve_queue(h: &Call, page: usize) -> String {
     {
         let n = n + start;
         let data = song.data::<TrackData>();
-        let metadata = &data.metadata;
+        let metadata = data.metadata();
         let duration = &metadata.duration;
         if !reply.is_empty() {
             reply.push('\n');
@@ -52,6 +54,7 @@ pub async fn retrieve_queue(h: &Call, page: usize) -> String {
 
         let duration = match duration {
             Some(duration) => format_duration(*duration),
+            None if data.is_live() => "live".into(),
             None => "unknown".into(),
         };
 
@@ -65,7 +68,7 @@ pub async fn retrieve_queue(h: &Call, page: usize) -> String {
             (n.to_string(), String::new())
         };
 
-        reply.push_str(&format!("{left}: {}{right}", format_metadata(metadata),));
+        reply.push_str(&format!("{left}: {}{right}", format_metadata(&metadata),));
     }
     let current_page = page + 1;
     let pages_total = calc_pages(h.queue().len(), 10);
@@ -75,6 +78,32 @@ pub async fn retrieve_queue(h: &Call, page: usize) -> String {
     reply
 }
 
+/// The queue in a form that can be sent to remote clients.
+pub async fn snapshot(h: &Call) -> QueueSnapshot {
+    let queue = h.queue().current_queue();
+    let info = match queue.first() {
+        Some(current) => current.get_info().await.ok(),
+        None => None,
+    };
+    let tracks = queue
+        .iter()
+        .map(|song| {
+            let data = song.data::<TrackData>();
+            QueueEntry {
+                id: data.id,
+                track: data.info(),
+                queueable: data.queueable.clone(),
+                is_loop_enabled: data.is_loop_enabled.load(Ordering::SeqCst),
+            }
+        })
+        .collect();
+    QueueSnapshot {
+        position: info.as_ref().map(|info| info.position),
+        paused: info.is_some_and(|info| info.playing == PlayMode::Pause),
+        tracks,
+    }
+}
+
 fn make_buttons(page: usize, len: usize) -> CreateActionRow {
     CreateActionRow::Buttons(vec![
         CreateButton::new("prev_page")
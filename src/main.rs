@@ -1,8 +1,7 @@
 use std::env;
 use std::num::NonZeroU64;
-use std::sync::{Arc, LazyLock};
+use std::sync::Arc;
 
-use reqwest::Client;
 use restart::CallData;
 use serenity::client::ClientBuilder;
 use serenity::gateway::ActivityData;
@@ -25,28 +24,32 @@ pub type Command = poise::Command<Data, Error>;
 
 pub(crate) use serenity::client::Context as DiscordContext;
 
+mod attachment;
+mod auth;
+mod dashboard;
+mod direct;
+mod extractor;
+mod file;
+mod http;
+mod import;
+mod library;
 mod list;
 mod metadata;
+mod mpd;
+mod overlay;
 mod play;
+mod podcast;
 mod queue;
+mod radio;
+mod resolve;
 mod restart;
 mod search;
+mod settings;
+mod suggest;
 mod track;
 mod vc;
 mod ws;
 
-pub fn yt_dlp_extra_args() -> &'static [String] {
-    static EXTRA_ARGS: LazyLock<Vec<String>> = LazyLock::new(|| {
-        // TODO make this an env var
-        if let Ok(true) = std::fs::exists("./cookies.firefox-private.txt") {
-            vec!["--cookies".into(), "./cookies.firefox-private.txt".into()]
-        } else {
-            vec![]
-        }
-    });
-    &EXTRA_ARGS
-}
-
 macro_rules! commands {
     ($($i: ident),*$(,)?) => {
         pub fn register_commands(v: &mut Vec<crate::Command>) {
@@ -59,6 +62,14 @@ pub(crate) use commands;
 
 pub struct Data {
     client: reqwest::Client,
+    extractor: Arc<dyn extractor::Extractor>,
+    events: ws::Events,
+    tokens: Arc<auth::TokenStore>,
+    dashboard: Arc<dashboard::Dashboard>,
+    library: Arc<library::Library>,
+    podcasts: Arc<podcast::Podcasts>,
+    settings: Arc<settings::Settings>,
+    suggestions: Arc<suggest::Suggestions>,
 }
 
 fn main() {
@@ -73,12 +84,21 @@ fn all_commands() -> Vec<Command> {
     let mut v = Vec::new();
 
     play::register_commands(&mut v);
+    file::register_commands(&mut v);
+    attachment::register_commands(&mut v);
+    radio::register_commands(&mut v);
+    podcast::register_commands(&mut v);
+    import::register_commands(&mut v);
+    settings::register_commands(&mut v);
+    library::register_commands(&mut v);
     list::register_commands(&mut v);
     track::register_commands(&mut v);
     vc::register_commands(&mut v);
     queue::register_commands(&mut v);
     restart::register_commands(&mut v);
     search::register_commands(&mut v);
+    auth::register_commands(&mut v);
+    dashboard::register_commands(&mut v);
 
     v.push(register());
     v.push(help());
@@ -107,11 +127,16 @@ Source code available at: https://github.com/fee1-dead/lyrebird",
     Ok(())
 }
 
-async fn maybe_recover(ctx: &DiscordContext, client: Client) {
+async fn maybe_recover(
+    ctx: &DiscordContext,
+    sources: play::Sources,
+    events: ws::Events,
+    podcasts: Arc<podcast::Podcasts>,
+) {
     if let Ok(x) = env::var("RESTART_RECOVER_PATH") {
         let songbird = songbird::get(ctx).await.unwrap();
         tokio::spawn(async move {
-            if let Err(e) = maybe_recover_inner(songbird, x, client).await {
+            if let Err(e) = maybe_recover_inner(songbird, x, sources, events, podcasts).await {
                 warn!("Error occured while recovering: {e}");
             }
         });
@@ -121,7 +146,9 @@ async fn maybe_recover(ctx: &DiscordContext, client: Client) {
 async fn maybe_recover_inner(
     songbird: Arc<Songbird>,
     path: String,
-    client: Client,
+    sources: play::Sources,
+    events: ws::Events,
+    podcasts: Arc<podcast::Podcasts>,
 ) -> color_eyre::Result<()> {
     let f = fs::read_to_string(&path).await?;
     let _ = fs::remove_file(path).await;
@@ -132,13 +159,16 @@ async fn maybe_recover_inner(
         queue,
     } in values
     {
-        let Ok(call) = songbird.join(GuildId(guild), ChannelId(channel)).await else {
+        let guild = GuildId(guild);
+        let Ok(call) =
+            vc::join_channel(&songbird, guild, ChannelId(channel), &events, &podcasts).await
+        else {
             continue;
         };
-        let mut handler = call.lock().await;
         for q in queue {
-            let _ = play::enqueue(client.clone(), q, &mut handler).await;
+            let _ = play::enqueue(sources.clone(), guild, q, &call).await;
         }
+        events.emit(guild, ws::ServerMessage::QueueChanged);
     }
     Ok(())
 }
@@ -155,12 +185,92 @@ async fn main_inner() {
             .expect("bot owner ID should be non-zero"),
     );
 
+    let songbird = Songbird::serenity();
+    let events = ws::Events::new();
+    let tokens = auth::TokenStore::load()
+        .await
+        .expect("failed to load remote control tokens");
+
+    let library = library::Library::load()
+        .await
+        .expect("failed to load the library index");
+    library.clone().spawn_refresh();
+    let podcasts = podcast::Podcasts::load()
+        .await
+        .expect("failed to load podcasts");
+    let settings = settings::Settings::load()
+        .await
+        .expect("failed to load settings");
+    let dashboard = dashboard::Dashboard::new(env::var("PUBLIC_URL").ok(), tokens.clone());
+
+    let client = reqwest::Client::new();
+    let extractor: Arc<dyn extractor::Extractor> = Arc::new(extractor::YtDlp::from_env());
+    let sources = play::Sources {
+        client: client.clone(),
+        extractor: extractor.clone(),
+        events: events.clone(),
+    };
+
+    let remote = Arc::new(ws::Remote::new(
+        songbird.clone(),
+        sources.clone(),
+        events.clone(),
+        tokens.clone(),
+        podcasts.clone(),
+    ));
+
+    if let Ok(addr) = env::var("WS_ADDR") {
+        let addr = addr.parse().expect("WS_ADDR is not a valid socket address");
+        let server = ws::WsServer::new(remote.clone());
+        tokio::spawn(async move {
+            if let Err(e) = server.listen(addr).await {
+                warn!("websocket server stopped: {e}");
+            }
+        });
+    }
+
+    if let Ok(addr) = env::var("HTTP_ADDR") {
+        let addr = addr
+            .parse()
+            .expect("HTTP_ADDR is not a valid socket address");
+        let server = http::HttpServer::new(remote.clone(), dashboard.clone());
+        tokio::spawn(async move {
+            if let Err(e) = server.listen(addr).await {
+                warn!("http server stopped: {e}");
+            }
+        });
+    }
+
+    if let Ok(addr) = env::var("MPD_ADDR") {
+        let addr = addr
+            .parse()
+            .expect("MPD_ADDR is not a valid socket address");
+        let guild = env::var("MPD_GUILD").expect("Please set MPD_GUILD to use the MPD server");
+        let guild = NonZeroU64::new(guild.parse().expect("MPD_GUILD not correctly set"))
+            .expect("MPD_GUILD should be non-zero");
+        let server = mpd::MpdServer::new(remote.clone(), GuildId(guild));
+        tokio::spawn(async move {
+            if let Err(e) = server.listen(addr).await {
+                warn!("mpd server stopped: {e}");
+            }
+        });
+    }
+
     let framework = poise::FrameworkBuilder::default()
         .setup(|ctx, _ready, _framework| {
             Box::pin(async move {
-                let client = reqwest::Client::new();
-                maybe_recover(ctx, client.clone()).await;
-                Ok(Data { client })
+                maybe_recover(ctx, sources, events.clone(), podcasts.clone()).await;
+                Ok(Data {
+                    client,
+                    extractor,
+                    events,
+                    tokens,
+                    dashboard,
+                    library,
+                    podcasts,
+                    settings,
+                    suggestions: Default::default(),
+                })
             })
         })
         .options(poise::FrameworkOptions {
@@ -177,7 +287,7 @@ async fn main_inner() {
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
     let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
     let client = ClientBuilder::new(token, intents)
-        .register_songbird()
+        .register_songbird_with(songbird)
         .activity(ActivityData::watching("you"))
         .framework(framework)
         .await;
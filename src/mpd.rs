@@ -0,0 +1,607 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use songbird::id::GuildId;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::auth::{required_scope, Scope, Token};
+use crate::play::Queueable;
+use crate::ws::{ClientMessage, QueueEntry, QueueSnapshot, Remote, Response, ServerMessage};
+use crate::Error;
+
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_PASSWORD: u32 = 3;
+const ACK_ERROR_PERMISSION: u32 = 4;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_NO_EXIST: u32 = 50;
+const ACK_ERROR_SYSTEM: u32 = 52;
+
+const COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "command_list_begin",
+    "command_list_end",
+    "command_list_ok_begin",
+    "commands",
+    "currentsong",
+    "decoders",
+    "delete",
+    "deleteid",
+    "idle",
+    "listplaylists",
+    "move",
+    "moveid",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "password",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistinfo",
+    "plchanges",
+    "seek",
+    "seekcur",
+    "seekid",
+    "shuffle",
+    "stats",
+    "status",
+    "stop",
+    "swap",
+    "swapid",
+    "tagtypes",
+    "urlhandlers",
+];
+
+struct Ack {
+    code: u32,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Ack {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Speaks a subset of the [Music Player Daemon protocol], so that clients such as
+/// `mpc` and `ncmpcpp` can control the queue of a single guild.
+///
+/// [Music Player Daemon protocol]: https://mpd.readthedocs.io/en/latest/protocol.html
+pub struct MpdServer {
+    remote: Arc<Remote>,
+    guild: GuildId,
+    /// Bumped whenever the queue changes, reported as `playlist` in `status`.
+    version: AtomicU32,
+}
+
+impl MpdServer {
+    pub fn new(remote: Arc<Remote>, guild: GuildId) -> Self {
+        Self {
+            remote,
+            guild,
+            version: AtomicU32::new(1),
+        }
+    }
+
+    pub async fn listen(self, addr: SocketAddr) -> color_eyre::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(%addr, "mpd server listening");
+        let server = Arc::new(self);
+        tokio::spawn(server.clone().track_version());
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(stream).await {
+                    warn!(%peer, "mpd connection closed: {e}");
+                }
+            });
+        }
+    }
+
+    async fn track_version(self: Arc<Self>) {
+        let mut events = self.remote.events.subscribe();
+        loop {
+            match events.recv().await {
+                Ok((guild, message)) if guild == self.guild => {
+                    if subsystems(&message).contains(&"playlist") {
+                        self.version.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {
+                    self.version.fetch_add(1, Ordering::SeqCst);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn serve(&self, stream: TcpStream) -> Result<(), Error> {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut token = None;
+        // commands buffered inside a command list, and whether to send `list_OK` after each
+        let mut list: Option<(bool, Vec<Vec<String>>)> = None;
+        write.write_all(b"OK MPD 0.23.5\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let args = match split_args(&line) {
+                Ok(args) if !args.is_empty() => args,
+                Ok(_) => continue,
+                Err(ack) => {
+                    write.write_all(format_ack(&ack, 0, "").as_bytes()).await?;
+                    continue;
+                }
+            };
+            let mut out = String::new();
+            match (&mut list, &*args[0]) {
+                (None, "command_list_begin") => list = Some((false, Vec::new())),
+                (None, "command_list_ok_begin") => list = Some((true, Vec::new())),
+                (Some((list_ok, commands)), "command_list_end") => {
+                    let list_ok = *list_ok;
+                    let mut result = Ok(());
+                    for (i, args) in commands.iter().enumerate() {
+                        if let Err(ack) = self.command(&mut token, args, &mut out).await {
+                            result = Err(format_ack(&ack, i, &args[0]));
+                            break;
+                        }
+                        if list_ok {
+                            out.push_str("list_OK\n");
+                        }
+                    }
+                    list = None;
+                    match result {
+                        Ok(()) => out.push_str("OK\n"),
+                        Err(ack) => out.push_str(&ack),
+                    }
+                }
+                (Some((_, commands)), _) => {
+                    commands.push(args);
+                    continue;
+                }
+                (None, "close") => break,
+                (None, "idle") => {
+                    if !self
+                        .idle(&token, &args[1..], &mut lines, &mut write)
+                        .await?
+                    {
+                        break;
+                    }
+                    continue;
+                }
+                // only meaningful while idle
+                (None, "noidle") => continue,
+                (None, cmd) => match self.command(&mut token, &args, &mut out).await {
+                    Ok(()) => out.push_str("OK\n"),
+                    Err(ack) => out.push_str(&format_ack(&ack, 0, cmd)),
+                },
+            }
+            write.write_all(out.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for a change in one of the requested subsystems, or for `noidle`.
+    /// Returns `false` if the connection should be closed.
+    async fn idle(
+        &self,
+        token: &Option<Token>,
+        wanted: &[String],
+        lines: &mut Lines<BufReader<OwnedReadHalf>>,
+        write: &mut OwnedWriteHalf,
+    ) -> Result<bool, Error> {
+        if let Err(ack) = self.authorize(token, Scope::Read) {
+            write
+                .write_all(format_ack(&ack, 0, "idle").as_bytes())
+                .await?;
+            return Ok(true);
+        }
+        let mut events = self.remote.events.subscribe();
+        let changed: Vec<_> = loop {
+            tokio::select! {
+                line = lines.next_line() => match line? {
+                    Some(line) if line.trim() == "noidle" => break Vec::new(),
+                    // anything else while idle is a protocol violation
+                    _ => return Ok(false),
+                },
+                event = events.recv() => {
+                    let changed = match event {
+                        Ok((guild, message)) if guild == self.guild => subsystems(&message),
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(_)) => &["player", "playlist"],
+                        Err(RecvError::Closed) => return Ok(false),
+                    };
+                    let changed: Vec<_> = changed
+                        .iter()
+                        .filter(|s| wanted.is_empty() || wanted.iter().any(|w| w == *s))
+                        .collect();
+                    if !changed.is_empty() {
+                        break changed;
+                    }
+                }
+            }
+        };
+        let mut out = String::new();
+        for subsystem in changed {
+            writeln!(out, "changed: {subsystem}").unwrap();
+        }
+        out.push_str("OK\n");
+        write.write_all(out.as_bytes()).await?;
+        Ok(true)
+    }
+
+    fn authorize(&self, token: &Option<Token>, scope: Scope) -> Result<Token, Ack> {
+        let token = token
+            .as_ref()
+            .and_then(|t| self.remote.tokens.current(t))
+            .ok_or_else(|| Ack::new(ACK_ERROR_PERMISSION, "you don't have permission"))?;
+        token
+            .authorize(self.guild, scope)
+            .map_err(|e| Ack::new(ACK_ERROR_PERMISSION, e.to_string()))?;
+        Ok(token)
+    }
+
+    async fn run(&self, token: &Option<Token>, message: ClientMessage) -> Result<Response, Ack> {
+        let token = self.authorize(token, required_scope(&message))?;
+        self.remote
+            .handle(&token, self.guild, message)
+            .await
+            .map_err(|e| Ack::new(ACK_ERROR_SYSTEM, e.to_string()))
+    }
+
+    async fn snapshot(&self, token: &Option<Token>) -> Result<QueueSnapshot, Ack> {
+        match self.run(token, ClientMessage::QueueSnapshot).await {
+            Ok(Response::Queue(snapshot)) => Ok(snapshot),
+            Ok(_) => unreachable!("QueueSnapshot is always answered with a queue"),
+            // not being in a voice channel looks like an empty queue to MPD clients
+            Err(ack) if ack.code == ACK_ERROR_SYSTEM => Ok(QueueSnapshot {
+                position: None,
+                paused: false,
+                tracks: Vec::new(),
+            }),
+            Err(ack) => Err(ack),
+        }
+    }
+
+    async fn position_of(&self, token: &Option<Token>, id: &str) -> Result<usize, Ack> {
+        let id = parse::<u32>(id)?;
+        self.snapshot(token)
+            .await?
+            .tracks
+            .iter()
+            .position(|t| t.id == id)
+            .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))
+    }
+
+    async fn command(
+        &self,
+        token: &mut Option<Token>,
+        args: &[String],
+        out: &mut String,
+    ) -> Result<(), Ack> {
+        use ClientMessage::*;
+        let arg = |i: usize| {
+            args.get(i)
+                .map(String::as_str)
+                .ok_or_else(|| Ack::new(ACK_ERROR_ARG, "too few arguments"))
+        };
+        let message = match &*args[0] {
+            "ping" | "tagtypes" | "outputs" | "decoders" | "listplaylists" | "notcommands" => {
+                return Ok(());
+            }
+            "commands" => {
+                for cmd in COMMANDS {
+                    writeln!(out, "command: {cmd}").unwrap();
+                }
+                return Ok(());
+            }
+            "urlhandlers" => {
+                out.push_str("handler: http://\nhandler: https://\n");
+                return Ok(());
+            }
+            "password" => {
+                let secret = arg(1)?;
+                let found = self
+                    .remote
+                    .tokens
+                    .get(secret)
+                    .filter(|t| t.authorize(self.guild, Scope::Read).is_ok())
+                    .ok_or_else(|| Ack::new(ACK_ERROR_PASSWORD, "incorrect password"))?;
+                *token = Some(found);
+                return Ok(());
+            }
+            "status" => {
+                let snapshot = self.snapshot(token).await?;
+                self.write_status(&snapshot, out);
+                return Ok(());
+            }
+            "stats" => {
+                let snapshot = self.snapshot(token).await?;
+                writeln!(out, "songs: {}", snapshot.tracks.len()).unwrap();
+                return Ok(());
+            }
+            "currentsong" => {
+                let snapshot = self.snapshot(token).await?;
+                if let Some(current) = snapshot.tracks.first() {
+                    write_song(current, 0, out);
+                }
+                return Ok(());
+            }
+            "playlistinfo" | "plchanges" => {
+                let snapshot = self.snapshot(token).await?;
+                let range = match (&*args[0], args.get(1)) {
+                    ("playlistinfo", Some(range)) => parse_range(range)?,
+                    _ => 0..usize::MAX,
+                };
+                for (pos, entry) in snapshot.tracks.iter().enumerate() {
+                    if range.contains(&pos) {
+                        write_song(entry, pos, out);
+                    }
+                }
+                return Ok(());
+            }
+            "add" | "addid" => {
+                let before = self.snapshot(token).await?.tracks.len();
+                self.run(
+                    token,
+                    Enqueue(Queueable::Ytdl {
+                        arg: arg(1)?.to_owned(),
+                    }),
+                )
+                .await?;
+                if args[0] == "addid" {
+                    let snapshot = self.snapshot(token).await?;
+                    if let Some(entry) = snapshot.tracks.get(before) {
+                        writeln!(out, "Id: {}", entry.id).unwrap();
+                    }
+                }
+                return Ok(());
+            }
+            "delete" => Remove(parse(arg(1)?)?),
+            "deleteid" => Remove(self.position_of(token, arg(1)?).await?),
+            "move" => Move {
+                from: parse(arg(1)?)?,
+                to: parse(arg(2)?)?,
+            },
+            "moveid" => Move {
+                from: self.position_of(token, arg(1)?).await?,
+                to: parse(arg(2)?)?,
+            },
+            "swap" => Swap {
+                a: parse(arg(1)?)?,
+                b: parse(arg(2)?)?,
+            },
+            "swapid" => Swap {
+                a: self.position_of(token, arg(1)?).await?,
+                b: self.position_of(token, arg(2)?).await?,
+            },
+            "clear" => ClearAll,
+            "shuffle" => Shuffle,
+            "next" => Skip,
+            // stopping would throw away the current track, pause instead
+            "stop" => Pause,
+            "pause" => match args.get(1).map(String::as_str) {
+                Some("1") => Pause,
+                Some("0") => Resume,
+                _ if self.snapshot(token).await?.paused => Resume,
+                _ => Pause,
+            },
+            "play" | "playid" => {
+                let pos = match (&*args[0], args.get(1)) {
+                    (_, None) => 0,
+                    ("play", Some(pos)) => parse(pos)?,
+                    (_, Some(id)) => self.position_of(token, id).await?,
+                };
+                if pos > 0 {
+                    // bring the song up next and skip to it
+                    self.run(token, Move { from: pos, to: 1 }).await?;
+                    Skip
+                } else {
+                    Resume
+                }
+            }
+            "seekcur" => {
+                let time = arg(1)?;
+                let seconds = parse::<f64>(time)?;
+                let position = if time.starts_with(['+', '-']) {
+                    let current = self.snapshot(token).await?.position.unwrap_or_default();
+                    (current.as_secs_f64() + seconds).max(0.0)
+                } else {
+                    seconds
+                };
+                Seek(parse_seconds(position)?)
+            }
+            "seek" | "seekid" => {
+                let pos = match &*args[0] {
+                    "seek" => parse(arg(1)?)?,
+                    _ => self.position_of(token, arg(1)?).await?,
+                };
+                if pos != 0 {
+                    return Err(Ack::new(ACK_ERROR_ARG, "can only seek in the current song"));
+                }
+                Seek(parse_seconds(parse::<f64>(arg(2)?)?.max(0.0))?)
+            }
+            cmd => {
+                return Err(Ack::new(
+                    ACK_ERROR_UNKNOWN,
+                    format!("unknown command \"{cmd}\""),
+                ))
+            }
+        };
+        self.run(token, message).await?;
+        Ok(())
+    }
+
+    fn write_status(&self, snapshot: &QueueSnapshot, out: &mut String) {
+        let state = match (snapshot.tracks.is_empty(), snapshot.paused) {
+            (true, _) => "stop",
+            (false, true) => "pause",
+            (false, false) => "play",
+        };
+        let current = snapshot.tracks.first();
+        let repeat = current.is_some_and(|t| t.is_loop_enabled) as u8;
+        writeln!(out, "volume: -1").unwrap();
+        writeln!(out, "repeat: {repeat}").unwrap();
+        writeln!(out, "random: 0").unwrap();
+        writeln!(out, "single: {repeat}").unwrap();
+        // played songs are removed from the queue
+        writeln!(out, "consume: 1").unwrap();
+        writeln!(out, "playlist: {}", self.version.load(Ordering::SeqCst)).unwrap();
+        writeln!(out, "playlistlength: {}", snapshot.tracks.len()).unwrap();
+        writeln!(out, "state: {state}").unwrap();
+        if let Some(current) = current {
+            writeln!(out, "song: 0").unwrap();
+            writeln!(out, "songid: {}", current.id).unwrap();
+            let elapsed = snapshot.position.unwrap_or_default().as_secs_f64();
+            writeln!(out, "elapsed: {elapsed:.3}").unwrap();
+            if let Some(duration) = current.track.duration {
+                let duration = duration.as_secs_f64();
+                writeln!(out, "time: {}:{}", elapsed as u64, duration as u64).unwrap();
+                writeln!(out, "duration: {duration:.3}").unwrap();
+            }
+        }
+        if let Some(next) = snapshot.tracks.get(1) {
+            writeln!(out, "nextsong: 1").unwrap();
+            writeln!(out, "nextsongid: {}", next.id).unwrap();
+        }
+    }
+}
+
+fn write_song(entry: &QueueEntry, pos: usize, out: &mut String) {
+    let file = match &entry.queueable {
+        Queueable::Ytdl { arg } => arg.clone(),
+        Queueable::File { path } => path.display().to_string(),
+        Queueable::Http { url }
+        | Queueable::Radio { url }
+        | Queueable::Attachment { url, .. }
+        | Queueable::Podcast { url, .. } => url.clone(),
+    };
+    writeln!(out, "file: {file}").unwrap();
+    if let Some(artist) = &entry.track.artist {
+        writeln!(out, "Artist: {artist}").unwrap();
+    }
+    if let Some(title) = &entry.track.title {
+        writeln!(out, "Title: {title}").unwrap();
+    }
+    if let Some(duration) = entry.track.duration {
+        writeln!(out, "Time: {}", duration.as_secs()).unwrap();
+        writeln!(out, "duration: {:.3}", duration.as_secs_f64()).unwrap();
+    }
+    writeln!(out, "Pos: {pos}").unwrap();
+    writeln!(out, "Id: {}", entry.id).unwrap();
+}
+
+/// Which MPD subsystems an event counts as a change in.
+fn subsystems(message: &ServerMessage) -> &'static [&'static str] {
+    match message {
+        ServerMessage::QueueChanged => &["playlist"],
+        // finished tracks are removed from the queue
+        ServerMessage::TrackStarted(_) | ServerMessage::TrackEnded(_) => &["player", "playlist"],
+        ServerMessage::TrackErrored { .. }
+        | ServerMessage::Paused
+        | ServerMessage::Resumed
+        | ServerMessage::Seeked(_)
+        | ServerMessage::Joined { .. }
+        | ServerMessage::Left => &["player"],
+    }
+}
+
+fn format_ack(ack: &Ack, index: usize, command: &str) -> String {
+    format!("ACK [{}@{index}] {{{command}}} {}\n", ack.code, ack.message)
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T, Ack> {
+    s.parse()
+        .map_err(|_| Ack::new(ACK_ERROR_ARG, format!("invalid argument \"{s}\"")))
+}
+
+/// Turns seconds into a position, rejecting ones like `inf` that don't fit.
+fn parse_seconds(seconds: f64) -> Result<Duration, Ack> {
+    Duration::try_from_secs_f64(seconds).map_err(|e| Ack::new(ACK_ERROR_ARG, e.to_string()))
+}
+
+/// Parses `POS` or `START:END`, where `END` may be left out.
+fn parse_range(s: &str) -> Result<std::ops::Range<usize>, Ack> {
+    match s.split_once(':') {
+        Some((start, "")) => Ok(parse(start)?..usize::MAX),
+        Some((start, end)) => Ok(parse(start)?..parse(end)?),
+        None => {
+            let pos = parse(s)?;
+            Ok(pos..pos.saturating_add(1))
+        }
+    }
+}
+
+/// Splits a command line into arguments, which may be quoted with `"`.
+fn split_args(line: &str) -> Result<Vec<String>, Ack> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => arg.extend(chars.next()),
+                    Some(c) => arg.push(c),
+                    None => return Err(Ack::new(ACK_ERROR_ARG, "missing closing quote")),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, split_args};
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("3").ok(), Some(3..4));
+        assert_eq!(parse_range("2:5").ok(), Some(2..5));
+        assert_eq!(parse_range("2:").ok(), Some(2..usize::MAX));
+        let max = usize::MAX.to_string();
+        assert_eq!(parse_range(&max).ok(), Some(usize::MAX..usize::MAX));
+        assert!(parse_range("a:5").is_err());
+        assert!(parse_range("-1").is_err());
+    }
+
+    #[test]
+    fn splits_quoted_args() {
+        assert_eq!(
+            split_args("  add  song.mp3 ").ok(),
+            Some(vec!["add".into(), "song.mp3".into()])
+        );
+        assert_eq!(
+            split_args(r#"add "a \"b\" c" """#).ok(),
+            Some(vec!["add".into(), r#"a "b" c"#.into(), String::new()])
+        );
+        assert!(split_args(r#"add "unclosed"#).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+use poise::futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+use songbird::id::GuildId;
+
+use crate::auth::{Scope, Token};
+use crate::http::{event_stream, ApiError, Authorized, Guild};
+use crate::ws::{ClientMessage, Remote, Response, TrackInfo};
+
+/// A now-playing page meant to be added to OBS as a browser source.
+pub fn router() -> Router<Arc<Remote>> {
+    Router::new()
+        .route("/guilds/{guild}/overlay", get(page))
+        .route("/guilds/{guild}/overlay/events", get(events))
+}
+
+/// What the overlay shows, sent whenever something changes.
+#[derive(Serialize)]
+struct NowPlaying {
+    track: Option<TrackInfo>,
+    /// Seconds into the track.
+    elapsed: f64,
+    paused: bool,
+}
+
+async fn now_playing(remote: &Remote, token: &Token, guild: GuildId) -> NowPlaying {
+    let snapshot = match remote
+        .handle(token, guild, ClientMessage::QueueSnapshot)
+        .await
+    {
+        Ok(Response::Queue(snapshot)) => snapshot,
+        // not in a voice channel
+        _ => {
+            return NowPlaying {
+                track: None,
+                elapsed: 0.0,
+                paused: false,
+            }
+        }
+    };
+    NowPlaying {
+        track: snapshot.tracks.into_iter().next().map(|entry| entry.track),
+        elapsed: snapshot.position.unwrap_or_default().as_secs_f64(),
+        paused: snapshot.paused,
+    }
+}
+
+async fn page(
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+) -> Result<Html<&'static str>, ApiError> {
+    token
+        .authorize(GuildId(guild), Scope::Read)
+        .map_err(|e| ApiError::Forbidden(e.to_string()))?;
+    Ok(Html(include_str!("overlay.html")))
+}
+
+async fn events(
+    State(remote): State<Arc<Remote>>,
+    Authorized(token): Authorized,
+    Path(guild): Guild,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let guild = GuildId(guild);
+    token
+        .authorize(guild, Scope::Read)
+        .map_err(|e| ApiError::Forbidden(e.to_string()))?;
+    // send the current state right away, then again after every event
+    let changes = stream::once(async {})
+        .chain(event_stream(remote.clone(), token.clone(), guild).map(|_| ()));
+    let updates = changes.then(move |()| {
+        let remote = remote.clone();
+        let token = token.clone();
+        async move { Event::default().json_data(now_playing(&remote, &token, guild).await) }
+    });
+    Ok(Sse::new(updates).keep_alive(KeepAlive::default()))
+}
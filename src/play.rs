@@ -1,51 +1,117 @@
-use std::process::Stdio;
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
-use poise::{CreateReply, ReplyHandle};
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude::{
+    ButtonStyle, CreateActionRow, CreateButton, CreateInteractionResponse, EditMessage, Message,
+};
+use poise::{ChoiceParameter, CreateReply, ReplyHandle};
 use rand::rng;
 use rand::seq::IndexedRandom;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use songbird::input::{AuxMetadata, Input, YoutubeDl};
-use songbird::tracks::Track;
-use tokio::process::Command;
+use serenity::prelude::Mutex;
+use songbird::id::GuildId;
+use songbird::input::{AuxMetadata, Input};
+use songbird::tracks::{Track, TrackQueue};
+use songbird::Call;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::{pin, select, spawn};
+use tracing::{error, warn};
 
+use crate::attachment::Attachment;
+use crate::direct::{is_direct_audio, DirectAudio};
+use crate::extractor::{metadata_error, Extractor, Output, PLACE_IN_LINE};
+use crate::file::LocalFile;
 use crate::metadata::format_metadata;
+use crate::podcast::EpisodeAudio;
+use crate::queue::remove_track;
+use crate::radio::Radio;
+use crate::resolve::best_match;
+use crate::search::{default_provider, Provider};
+use crate::suggest::autocomplete_track;
 use crate::track::TrackData;
-use crate::{yt_dlp_extra_args, CommandResult, Context, Error};
+use crate::ws::{Events, ServerMessage};
+use crate::{CommandResult, Context, DiscordContext, Error};
 
 use crate::vc::enter_vc;
 
 crate::commands!(play, splay, playall, playrand, playrange);
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum Queueable {
-    Ytdl { arg: String },
-}
+pub use lyrebird_protocol::Queueable;
+
+/// Clients are told about resolved metadata after this many entries.
+const METADATA_BATCH: usize = 10;
 
-pub trait HasClient {
-    fn client(self) -> Client;
+/// How many entries of one playlist are looked up at once. yt-dlp's
+/// `YT_DLP_BACKGROUND_PROCESSES` limit applies across all of them.
+const METADATA_LOOKUPS: usize = 4;
+
+/// What inputs are made with.
+pub trait HasSources {
+    fn client(&self) -> Client;
+    fn extractor(&self) -> Arc<dyn Extractor>;
+    fn events(&self) -> Events;
 }
 
-impl HasClient for Context<'_> {
-    fn client(self) -> Client {
+impl HasSources for Context<'_> {
+    fn client(&self) -> Client {
         self.data().client.clone()
     }
+
+    fn extractor(&self) -> Arc<dyn Extractor> {
+        self.data().extractor.clone()
+    }
+
+    fn events(&self) -> Events {
+        self.data().events.clone()
+    }
 }
 
-impl HasClient for Client {
-    fn client(self) -> Client {
-        self
+/// [`HasSources`] for places without a command context.
+#[derive(Clone)]
+pub struct Sources {
+    pub client: Client,
+    pub extractor: Arc<dyn Extractor>,
+    pub events: Events,
+}
+
+impl HasSources for Sources {
+    fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    fn extractor(&self) -> Arc<dyn Extractor> {
+        self.extractor.clone()
     }
+
+    fn events(&self) -> Events {
+        self.events.clone()
+    }
+}
+
+pub trait IntoInput {
+    /// `track` is the data of the track the input will be played in, in the
+    /// queue of `guild`.
+    fn into_input(self, x: impl HasSources, guild: GuildId, track: Weak<TrackData>) -> Input;
 }
 
-impl Queueable {
-    pub fn into_input(self, x: impl HasClient) -> Input {
+impl IntoInput for Queueable {
+    fn into_input(self, x: impl HasSources, guild: GuildId, track: Weak<TrackData>) -> Input {
         match self {
-            Queueable::Ytdl { arg } => YoutubeDl::new(x.client(), arg)
-                .user_args(yt_dlp_extra_args().to_vec())
-                .into(),
+            Queueable::Ytdl { arg } => x.extractor().resolve(x.client(), arg),
+            Queueable::File { path } => LocalFile::new(path).into(),
+            Queueable::Http { url } => DirectAudio::new(x.client(), url).into(),
+            Queueable::Radio { url } => {
+                Radio::new(x.client(), url, x.events(), guild, track).into()
+            }
+            Queueable::Attachment { url, filename } => {
+                Attachment::new(x.client(), url, filename).into()
+            }
+            Queueable::Podcast { url, show, title } => {
+                EpisodeAudio::new(x.client(), url, show, title).into()
+            }
         }
     }
 }
@@ -54,9 +120,19 @@ impl Queueable {
 /// Add a song to queue from the given URL.
 pub async fn play(
     ctx: Context<'_>,
-    #[description = "URL of song to play"] url: String,
+    #[description = "URL of song to play"]
+    #[autocomplete = "autocomplete_track"]
+    url: String,
 ) -> CommandResult {
-    play_common(ctx, url, |_, url| Queueable::Ytdl { arg: url }, false).await
+    ctx.defer().await?;
+    // links straight to audio files don't need yt-dlp
+    let mk: fn(Context<'_>, String) -> Queueable =
+        if is_direct_audio(&ctx.data().client, &url).await {
+            |_, url| Queueable::Http { url }
+        } else {
+            |_, url| Queueable::Ytdl { arg: url }
+        };
+    play_common(ctx, url, mk, false).await
 }
 
 #[poise::command(slash_command, category = "Enqueue")]
@@ -65,43 +141,34 @@ pub async fn splay(
     ctx: Context<'_>,
     #[rest]
     #[description = "keyword to search for"]
+    #[autocomplete = "autocomplete_track"]
     keyword: String,
+    #[description = "where to search, defaults to /searchprovider"] provider: Option<Provider>,
 ) -> CommandResult {
-    play_common(
-        ctx,
-        keyword,
-        |_, term| Queueable::Ytdl {
-            arg: format!("ytsearch1:{term}"),
-        },
-        false,
-    )
-    .await
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Output {
-    pub url: String,
-    ie_key: String,
-    #[serde(default)]
-    pub title: Option<String>,
-    #[serde(default)]
-    pub channel: Option<String>,
-    _type: String,
-}
-
-impl Output {
-    pub fn is_playable(&self) -> bool {
-        self._type == "url" && !self.is_playlist()
-    }
-    pub fn is_playlist(&self) -> bool {
-        self.ie_key == "YoutubePlaylist" || self.ie_key == "YoutubeTab"
+    ctx.defer().await?;
+    // picking a suggestion fills in its URL
+    if keyword.starts_with("http") {
+        return play_common(ctx, keyword, |_, arg| Queueable::Ytdl { arg }, false).await;
     }
+    let provider = provider.unwrap_or_else(|| default_provider(ctx));
+    let Some(q) = in_line(ctx, best_match(provider, ctx.data(), &keyword, None)).await? else {
+        ctx.say(format!(
+            "No results for \"{keyword}\" on {}",
+            provider.name()
+        ))
+        .await?;
+        return Ok(());
+    };
+    enter_vc(ctx, true, |handler, ctx| async move {
+        play_multiple(ctx, vec![q], &handler).await
+    })
+    .await
 }
 
 pub async fn play_multiple(
     ctx: Context<'_>,
     input: Vec<Queueable>,
-    handler: &mut songbird::Call,
+    handler: &Mutex<Call>,
 ) -> CommandResult {
     let mut cnt = 0usize;
     let mut msg = None;
@@ -115,45 +182,291 @@ pub async fn play_multiple(
     Ok(())
 }
 
-#[poise::command(slash_command, category = "Enqueue")]
-/// Play all songs from a given playlist
-pub async fn playall(
+/// A message with a Cancel button, kept up to date while a command and the
+/// work it leaves running in the background go on.
+///
+/// The message is edited directly rather than through the interaction, whose
+/// token expires long before a big playlist is looked up.
+struct Progress {
+    discord: DiscordContext,
+    message: Message,
+    cancelled: watch::Receiver<bool>,
+}
+
+fn cancel_button() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new("cancel")
+        .label("Cancel")
+        .style(ButtonStyle::Danger)])
+}
+
+impl Progress {
+    async fn start(ctx: Context<'_>, text: String) -> Result<Self, Error> {
+        let message = ctx
+            .send(
+                CreateReply::default()
+                    .content(text)
+                    .components(vec![cancel_button()]),
+            )
+            .await?
+            .into_message()
+            .await?;
+        let clicks = message.await_component_interaction(ctx);
+        let (tx, cancelled) = watch::channel(false);
+        let discord = ctx.serenity_context().clone();
+        let acknowledge = discord.clone();
+        spawn(async move {
+            let mut clicks = clicks.stream();
+            select! {
+                Some(click) = clicks.next() => {
+                    tx.send_replace(true);
+                    if let Err(e) = click
+                        .create_response(&acknowledge, CreateInteractionResponse::Acknowledge)
+                        .await
+                    {
+                        error!("could not acknowledge cancel: {e}");
+                    }
+                }
+                // the work finished first
+                () = tx.closed() => {}
+            }
+        });
+        Ok(Progress {
+            discord,
+            message,
+            cancelled,
+        })
+    }
+
+    /// Runs `f` until it is done, or `None` if Cancel is clicked first.
+    async fn unless_cancelled<T>(&self, f: impl Future<Output = T>) -> Option<T> {
+        let mut cancelled = self.cancelled.clone();
+        select! {
+            out = f => Some(out),
+            Ok(_) = cancelled.wait_for(|&c| c) => None,
+        }
+    }
+
+    async fn update(&mut self, text: String) -> Result<(), Error> {
+        let edit = EditMessage::new()
+            .content(text)
+            .components(vec![cancel_button()]);
+        self.message.edit(&self.discord, edit).await?;
+        Ok(())
+    }
+
+    /// Resolves once Cancel is clicked.
+    async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.clone();
+        // the sender is only dropped without a click once this is dropped
+        let _ = cancelled.wait_for(|&c| c).await;
+    }
+
+    /// Shows `text` and removes the button.
+    async fn finish(mut self, text: String) -> CommandResult {
+        let edit = EditMessage::new().content(text).components(vec![]);
+        self.message.edit(&self.discord, edit).await?;
+        Ok(())
+    }
+}
+
+/// Lists the playlist at `url` and queues the entries `pick` keeps, showing
+/// progress with a button to stop.
+async fn play_playlist(
     ctx: Context<'_>,
-    #[description = "url of playlist"] url: String,
+    url: String,
+    range: Option<String>,
+    pick: impl FnOnce(Vec<Output>) -> Vec<Output> + Send,
 ) -> CommandResult {
     ctx.defer().await?;
-    let cmd = Command::new("yt-dlp")
-        .args(yt_dlp_extra_args())
-        .arg("--flat-playlist")
-        .arg("-s")
-        .arg("-j")
-        .arg(url)
-        .stderr(Stdio::null())
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .output()
-        .await?;
+    enter_vc(ctx, true, |handler, ctx| async move {
+        let progress = Progress::start(ctx, "Listing the playlist...".into()).await?;
+        let listing = ctx.data().extractor.flat_playlist(&url, range.as_deref());
+        let entries = match progress.unless_cancelled(in_line(ctx, listing)).await {
+            Some(Ok(entries)) => pick(entries),
+            Some(Err(e)) => {
+                return progress
+                    .finish(format!("Could not list the playlist: {e}"))
+                    .await
+            }
+            None => return progress.finish("Cancelled".into()).await,
+        };
+        play_listed(ctx, entries, handler, progress).await
+    })
+    .await
+}
 
-    // TODO use exit_ok
+/// Queues playlist entries without waiting for yt-dlp to resolve each of
+/// them, showing what the listing knows until [`fill_metadata`] is done.
+/// `progress` is kept up to date by it, with the button to cancel.
+async fn play_listed(
+    ctx: Context<'_>,
+    entries: Vec<Output>,
+    handler: Arc<Mutex<Call>>,
+    mut progress: Progress,
+) -> CommandResult {
+    let guild = GuildId::from(ctx.guild_id().unwrap());
+    let total = entries.len();
+    let mut tracks = Vec::with_capacity(total);
+    // Cancel takes back what was queued, so it isn't checked in between
+    for entry in &entries {
+        let q = Queueable::Ytdl {
+            arg: entry.url.clone(),
+        };
+        let data = Arc::new(TrackData::new(q.clone()));
+        let metadata = entry.metadata();
+        // songbird would otherwise run yt-dlp for the duration
+        let preload = preload_time(&metadata);
+        *data.metadata.write().unwrap() = metadata;
+        let input = q.into_input(ctx, guild, Arc::downgrade(&data));
+        tracks.push((entry.url.clone(), Arc::downgrade(&data)));
+        // the lock is taken for each song so other commands aren't held up
+        handler
+            .lock()
+            .await
+            .enqueue_with_preload(Track::new_with_data(input, data), preload);
+    }
+    ctx.data().events.emit(guild, ServerMessage::QueueChanged);
+    let sources = Sources {
+        client: ctx.client(),
+        extractor: ctx.extractor(),
+        events: ctx.events(),
+    };
+    progress
+        .update(format!("Queued {total} songs, looking up their details..."))
+        .await?;
+    spawn(async move {
+        let text = fill_metadata(sources, guild, handler, tracks, &mut progress).await;
+        if let Err(e) = progress.finish(text).await {
+            warn!(%e, "could not show that a playlist was queued");
+        }
+    });
+    Ok(())
+}
 
-    let s = String::from_utf8(cmd.stdout)?;
+/// Looks up the full metadata of `tracks`, a few at a time, for those still
+/// in the queue. Ones that can't be played are taken out of it, like
+/// [`enqueue`] would have refused them. Clicking Cancel takes all of them
+/// out, in case the playlist was queued by mistake. Returns what happened.
+async fn fill_metadata(
+    sources: Sources,
+    guild: GuildId,
+    handler: Arc<Mutex<Call>>,
+    tracks: Vec<(String, Weak<TrackData>)>,
+    progress: &mut Progress,
+) -> String {
+    let events = &sources.events;
+    let total = tracks.len();
+    let ids = tracks
+        .iter()
+        .filter_map(|(_, track)| Some(track.upgrade()?.id))
+        .collect::<Vec<_>>();
+    let mut tracks = tracks.into_iter();
+    let mut lookups = JoinSet::new();
+    let mut done = 0;
+    let mut unplayable = 0;
+    loop {
+        while lookups.len() < METADATA_LOOKUPS {
+            let Some((url, track)) = tracks.next() else {
+                break;
+            };
+            // skipped or removed tracks don't need it anymore
+            if track.strong_count() == 0 {
+                continue;
+            }
+            let extractor = sources.extractor.clone();
+            lookups.spawn(async move {
+                let result = extractor.lookup(&url).await;
+                (url, track, result)
+            });
+        }
+        let joined = select! {
+            joined = lookups.join_next() => joined,
+            () = progress.cancelled() => {
+                // dropping the lookups kills their yt-dlp processes
+                drop(lookups);
+                let call = handler.lock().await;
+                let queue = call.queue();
+                let mut removed = remove_queued(queue, &ids);
+                if let Some(current) = queue.current() {
+                    if ids.contains(&current.data::<TrackData>().id) {
+                        let _ = queue.skip();
+                        removed += 1;
+                    }
+                }
+                drop(call);
+                events.emit(guild, ServerMessage::QueueChanged);
+                return format!("Cancelled, took {removed} of the {total} songs back out of the queue");
+            }
+        };
+        let Some(joined) = joined else {
+            break;
+        };
+        let Ok((url, track, result)) = joined else {
+            continue;
+        };
+        match result {
+            Ok(metadata) => {
+                if let Some(track) = track.upgrade() {
+                    *track.metadata.write().unwrap() = metadata;
+                }
+            }
+            Err(e) => {
+                warn!(%e, url, "removing a song that can't be played");
+                if let Some(track) = track.upgrade() {
+                    let queue = handler.lock().await.queue().clone();
+                    if remove_queued(&queue, &[track.id]) > 0 {
+                        unplayable += 1;
+                        events.emit(guild, ServerMessage::QueueChanged);
+                    }
+                }
+            }
+        }
+        done += 1;
+        // let clients show the new titles as they come in
+        if done % METADATA_BATCH == 0 {
+            events.emit(guild, ServerMessage::QueueChanged);
+            let text = format!("Queued {total} songs, looked up {done}/{total}...");
+            if let Err(e) = progress.update(text).await {
+                warn!(%e, "could not show playlist progress");
+            }
+        }
+    }
+    events.emit(guild, ServerMessage::QueueChanged);
+    match unplayable {
+        0 => format!("Queued {total} songs"),
+        n => format!("Queued {total} songs, removed {n} that can't be played"),
+    }
+}
 
-    enter_vc(ctx, true, |handler, ctx| async move {
-        let parsed = s
-            .lines()
-            .map(serde_json::from_str::<Output>)
-            .collect::<Result<Vec<_>, _>>()?;
-        let inputs = parsed
-            .into_iter()
-            .filter(Output::is_playable)
-            .map(|x| Queueable::Ytdl { arg: x.url })
-            .collect::<Vec<_>>();
-        play_multiple(ctx, inputs, &mut *handler.lock().await).await?;
-        Ok(())
+/// Takes the tracks with the given [`TrackData`] ids out of `queue`, except
+/// the current one, returning how many were found.
+fn remove_queued(queue: &TrackQueue, ids: &[u32]) -> usize {
+    queue.modify_queue(|queue| {
+        let mut removed = 0;
+        let mut index = 1;
+        while index < queue.len() {
+            if ids.contains(&queue[index].data::<TrackData>().id)
+                && remove_track(queue, index).is_ok()
+            {
+                removed += 1;
+            } else {
+                index += 1;
+            }
+        }
+        removed
     })
-    .await?;
+}
 
-    Ok(())
+#[poise::command(slash_command, category = "Enqueue")]
+/// Play all songs from a given playlist
+pub async fn playall(
+    ctx: Context<'_>,
+    #[description = "url of playlist"] url: String,
+) -> CommandResult {
+    play_playlist(ctx, url, None, |entries| {
+        entries.into_iter().filter(Output::is_playable).collect()
+    })
+    .await
 }
 
 #[poise::command(slash_command, category = "Enqueue")]
@@ -163,39 +476,12 @@ pub async fn playrand(
     #[description = "url of the playlist"] url: String,
     #[description = "number of songs to play"] num: usize,
 ) -> CommandResult {
-    ctx.defer().await?;
-    let cmd = Command::new("yt-dlp")
-        .args(yt_dlp_extra_args())
-        .arg("--flat-playlist")
-        .arg("-s")
-        .arg("-j")
-        .arg(url)
-        .stderr(Stdio::null())
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .output()
-        .await?;
-    let s = String::from_utf8(cmd.stdout)?;
-    let outputs = s
-        .lines()
-        .map(serde_json::from_str::<Output>)
-        .filter_map(|x| x.ok().filter(|x| !x.is_playlist()))
-        .collect::<Vec<_>>();
-    let chooser = outputs
-        .choose_multiple(&mut rng(), num)
-        .cloned()
-        .collect::<Vec<_>>();
-    drop(outputs);
-    enter_vc(ctx, true, |handler, ctx| async move {
-        play_multiple(
-            ctx,
-            chooser
-                .into_iter()
-                .map(|x| Queueable::Ytdl { arg: x.url })
-                .collect(),
-            &mut *handler.lock().await,
-        )
-        .await
+    play_playlist(ctx, url, None, move |entries| {
+        let entries = entries
+            .into_iter()
+            .filter(|x| !x.is_playlist())
+            .collect::<Vec<_>>();
+        entries.choose_multiple(&mut rng(), num).cloned().collect()
     })
     .await
 }
@@ -207,43 +493,34 @@ pub async fn playrange(
     #[description = "url of the playlist"] url: String,
     #[description = "range"] range: String,
 ) -> CommandResult {
-    ctx.defer().await?;
-    let cmd = Command::new("yt-dlp")
-        .args(yt_dlp_extra_args())
-        .arg("--flat-playlist")
-        .arg("-s")
-        .arg("-j")
-        .arg("-I")
-        .arg(range)
-        .arg(url)
-        .stderr(Stdio::null())
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .output()
-        .await?;
-    let s = String::from_utf8(cmd.stdout)?;
-    let outputs = s
-        .lines()
-        .map(serde_json::from_str::<Output>)
-        .collect::<Result<Vec<_>, _>>()?;
-    let outputs = outputs
-        .into_iter()
-        .filter(|x| !x.is_playlist())
-        .collect::<Vec<_>>();
-    enter_vc(ctx, true, |handler, ctx| async move {
-        play_multiple(
-            ctx,
-            outputs
-                .into_iter()
-                .map(|x| Queueable::Ytdl { arg: x.url })
-                .collect(),
-            &mut *handler.lock().await,
-        )
-        .await
+    play_playlist(ctx, url, Some(range), |entries| {
+        entries.into_iter().filter(|x| !x.is_playlist()).collect()
     })
     .await
 }
 
+/// Runs `f`, telling the user their place in line while it waits for yt-dlp.
+pub async fn in_line<T>(ctx: Context<'_>, f: impl Future<Output = T>) -> T {
+    let (tx, mut rx) = watch::channel(0);
+    let f = PLACE_IN_LINE.scope(tx, f);
+    pin!(f);
+    let mut msg = None;
+    let out = loop {
+        select! {
+            out = &mut f => break out,
+            Ok(()) = rx.changed() => {
+                let place = *rx.borrow_and_update();
+                let text = format!("Waiting for yt-dlp, {place} in line...");
+                msg = maybe_edit(ctx, msg, text).await.ok();
+            }
+        }
+    };
+    if let Some(msg) = msg {
+        let _ = msg.delete(ctx).await;
+    }
+    out
+}
+
 async fn maybe_edit<'a>(
     ctx: Context<'a>,
     prev: Option<ReplyHandle<'a>>,
@@ -257,37 +534,60 @@ async fn maybe_edit<'a>(
     }
 }
 
+/// When songbird should start loading the song after this one.
+fn preload_time(metadata: &AuxMetadata) -> Option<Duration> {
+    metadata
+        .duration
+        .map(|d| d.saturating_sub(Duration::from_secs(5)))
+}
+
+/// Resolves `q` and adds it to the queue. The call is only locked once the
+/// song is resolved, so a slow yt-dlp doesn't hold up other commands.
 pub async fn enqueue(
-    client: impl HasClient,
+    sources: impl HasSources,
+    guild: GuildId,
     q: Queueable,
-    handler: &mut songbird::Call,
+    handler: &Mutex<Call>,
 ) -> color_eyre::Result<AuxMetadata> {
-    let mut input = q.clone().into_input(client);
-    let metadata = input.aux_metadata().await?;
-    let track = Track::new_with_data(
-        input,
-        Arc::new(TrackData {
-            metadata: metadata.clone(),
-            queueable: q,
-            is_loop_enabled: AtomicBool::new(false),
-        }),
-    );
-    handler.enqueue(track).await;
+    let data = Arc::new(TrackData::new(q.clone()));
+    let mut input = q.into_input(sources, guild, Arc::downgrade(&data));
+    let metadata = input.aux_metadata().await.map_err(metadata_error)?;
+    *data.metadata.write().unwrap() = metadata.clone();
+    // songbird would otherwise look up the metadata again for the preload time
+    handler
+        .lock()
+        .await
+        .enqueue_with_preload(Track::new_with_data(input, data), preload_time(&metadata));
     Ok(metadata)
 }
 
 async fn play_inner<'a>(
     ctx: Context<'a>,
     q: Queueable,
-    handler: &mut songbird::Call,
+    handler: &Mutex<Call>,
     edit: Option<ReplyHandle<'a>>,
 ) -> Result<ReplyHandle<'a>, Error> {
-    let metadata = enqueue(ctx, q, handler).await?;
+    let url = match &q {
+        Queueable::Ytdl { arg } => Some(arg.clone()),
+        _ => None,
+    };
+    let metadata = in_line(
+        ctx,
+        enqueue(ctx, ctx.guild_id().unwrap().into(), q, handler),
+    )
+    .await?;
+    if let Some(url) = metadata.source_url.clone().or(url) {
+        let title = metadata.title.clone().unwrap_or_else(|| url.clone());
+        ctx.data().suggestions.played(ctx.author().id, title, url);
+    }
+    ctx.data()
+        .events
+        .emit(ctx.guild_id().unwrap(), ServerMessage::QueueChanged);
     let msg = format!("Queued: {}", format_metadata(&metadata));
     maybe_edit(ctx, edit, msg).await
 }
 
-async fn play_common(
+pub async fn play_common(
     ctx: Context<'_>,
     term: String,
     mk: fn(Context<'_>, String) -> Queueable,
@@ -298,9 +598,8 @@ async fn play_common(
         ctx.say("Argument must be a valid URL").await?;
         return Ok(());
     }
-    enter_vc(ctx, true, |handler_lock, c| async move {
-        let mut handler = handler_lock.lock().await;
-        play_inner(c, mk(c, term), &mut handler, None).await?;
+    enter_vc(ctx, true, |handler, c| async move {
+        play_inner(c, mk(c, term), &handler, None).await?;
         Ok(())
     })
     .await
@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use std::env;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre::{bail, eyre};
+use poise::serenity_prelude::CreateEmbed;
+use reqwest::Client;
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+use songbird::id::GuildId;
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, Input};
+use songbird::tracks::{PlayMode, TrackState};
+use songbird::EventContext;
+use symphonia::core::io::MediaSource;
+use tokio::fs;
+use tracing::warn;
+
+use crate::direct::DirectAudio;
+use crate::metadata::format_duration;
+use crate::play::{play_multiple, Queueable};
+use crate::search::offer_choices;
+use crate::track::TrackData;
+use crate::vc::enter_vc;
+use crate::{CommandResult, Context, Error};
+
+crate::commands!(podcast);
+
+/// Stopping this close to the end counts as having finished the episode.
+const FINISHED_MARGIN: Duration = Duration::from_secs(30);
+
+pub struct Episode {
+    pub title: String,
+    pub url: String,
+    pub published: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+pub struct Feed {
+    pub title: String,
+    pub episodes: Vec<Episode>,
+}
+
+/// Reads durations like `3600`, `59:00` or `1:00:00`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    s.trim()
+        .split(':')
+        .try_fold(0u64, |total, part| {
+            total.checked_mul(60)?.checked_add(part.parse().ok()?)
+        })
+        .map(Duration::from_secs)
+}
+
+/// Finds a child element by its local name, so namespaces like `itunes:` don't matter.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn text_of(node: Node<'_, '_>, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|c| c.text())
+        .map(|t| t.trim().to_owned())
+}
+
+/// Parses an RSS or Atom feed, keeping only entries with audio attached.
+fn parse_feed(text: &str) -> Result<Feed, Error> {
+    let doc = roxmltree::Document::parse(text)?;
+    let root = doc.root_element();
+    let (channel, entry) = match root.tag_name().name() {
+        "rss" => (
+            child(root, "channel").ok_or_else(|| eyre!("RSS feed without a channel"))?,
+            "item",
+        ),
+        "feed" => (root, "entry"),
+        other => bail!("not an RSS or Atom feed: <{other}>"),
+    };
+    let episodes = channel
+        .children()
+        .filter(|c| c.is_element() && c.tag_name().name() == entry)
+        .filter_map(|item| {
+            let url = item
+                .children()
+                .find(|c| {
+                    let name = c.tag_name().name();
+                    name == "enclosure"
+                        || (name == "link" && c.attribute("rel") == Some("enclosure"))
+                })
+                .and_then(|c| c.attribute("url").or_else(|| c.attribute("href")))?;
+            Some(Episode {
+                title: text_of(item, "title").unwrap_or_else(|| url.to_owned()),
+                url: url.to_owned(),
+                published: text_of(item, "pubDate")
+                    .or_else(|| text_of(item, "published"))
+                    .or_else(|| text_of(item, "updated")),
+                duration: text_of(item, "duration").and_then(|d| parse_duration(&d)),
+            })
+        })
+        .collect();
+    Ok(Feed {
+        title: text_of(channel, "title").unwrap_or_default(),
+        episodes,
+    })
+}
+
+pub async fn fetch_feed(client: &Client, url: &str) -> Result<Feed, Error> {
+    let text = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    parse_feed(&text)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub url: String,
+    pub title: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct GuildPodcasts {
+    subscriptions: Vec<Subscription>,
+    /// Where each unfinished episode was stopped, by enclosure URL.
+    positions: HashMap<String, Duration>,
+}
+
+/// Podcast subscriptions and resume positions of each guild, persisted as JSON.
+pub struct Podcasts {
+    path: PathBuf,
+    guilds: Mutex<HashMap<NonZeroU64, GuildPodcasts>>,
+}
+
+impl Podcasts {
+    /// Loads podcasts from `PODCASTS_PATH`, defaulting to `./podcasts.json`.
+    pub async fn load() -> color_eyre::Result<Arc<Self>> {
+        let path = PathBuf::from(env::var("PODCASTS_PATH").unwrap_or("./podcasts.json".into()));
+        let guilds = match fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Arc::new(Podcasts {
+            path,
+            guilds: Mutex::new(guilds),
+        }))
+    }
+
+    pub fn subscriptions(&self, guild: GuildId) -> Vec<Subscription> {
+        self.guilds
+            .lock()
+            .unwrap()
+            .get(&guild.0)
+            .map_or_else(Vec::new, |g| g.subscriptions.clone())
+    }
+
+    /// Returns whether the guild wasn't already subscribed to the feed.
+    pub async fn subscribe(
+        &self,
+        guild: GuildId,
+        subscription: Subscription,
+    ) -> color_eyre::Result<bool> {
+        {
+            let mut guilds = self.guilds.lock().unwrap();
+            let subscriptions = &mut guilds.entry(guild.0).or_default().subscriptions;
+            if subscriptions.iter().any(|s| s.url == subscription.url) {
+                return Ok(false);
+            }
+            subscriptions.push(subscription);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Returns whether the guild was subscribed to the feed.
+    pub async fn unsubscribe(&self, guild: GuildId, url: &str) -> color_eyre::Result<bool> {
+        let removed = {
+            let mut guilds = self.guilds.lock().unwrap();
+            let subscriptions = &mut guilds.entry(guild.0).or_default().subscriptions;
+            let len = subscriptions.len();
+            subscriptions.retain(|s| s.url != url);
+            subscriptions.len() != len
+        };
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    pub fn position(&self, guild: GuildId, url: &str) -> Option<Duration> {
+        let guilds = self.guilds.lock().unwrap();
+        guilds.get(&guild.0)?.positions.get(url).copied()
+    }
+
+    async fn set_position(
+        &self,
+        guild: GuildId,
+        url: &str,
+        position: Option<Duration>,
+    ) -> color_eyre::Result<()> {
+        {
+            let mut guilds = self.guilds.lock().unwrap();
+            let positions = &mut guilds.entry(guild.0).or_default().positions;
+            match position {
+                Some(position) => positions.insert(url.to_owned(), position),
+                None => positions.remove(url),
+            };
+        }
+        self.save().await
+    }
+
+    /// Remembers where an episode was stopped, or forgets it once it has been
+    /// played to the end. Tracks that never started are left alone, so
+    /// clearing the queue doesn't lose positions.
+    pub async fn track_stopped(
+        &self,
+        guild: GuildId,
+        track: &TrackData,
+        state: &TrackState,
+    ) -> color_eyre::Result<()> {
+        let Queueable::Podcast { url, .. } = &track.queueable else {
+            return Ok(());
+        };
+        if state.play_time.is_zero() {
+            return Ok(());
+        }
+        let finished = matches!(state.playing, PlayMode::End)
+            || track
+                .metadata()
+                .duration
+                .is_some_and(|d| state.position + FINISHED_MARGIN >= d);
+        let position = (!finished).then_some(state.position);
+        self.set_position(guild, url, position).await
+    }
+
+    async fn save(&self) -> color_eyre::Result<()> {
+        let s = serde_json::to_string_pretty(&*self.guilds.lock().unwrap())?;
+        fs::write(&self.path, s).await?;
+        Ok(())
+    }
+}
+
+/// Seeks episodes to where they were stopped when they start, and records
+/// where they stop.
+pub struct PositionTracker {
+    pub guild: GuildId,
+    pub podcasts: Arc<Podcasts>,
+}
+
+#[async_trait]
+impl songbird::EventHandler for PositionTracker {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<songbird::Event> {
+        let EventContext::Track(e) = ctx else {
+            return None;
+        };
+        for (state, handle) in *e {
+            let data = handle.data::<TrackData>();
+            let Queueable::Podcast { url, .. } = &data.queueable else {
+                continue;
+            };
+            match state.playing {
+                PlayMode::Play if state.play_time.is_zero() => {
+                    if let Some(position) = self.podcasts.position(self.guild, url) {
+                        let _ = handle.seek(position);
+                    }
+                }
+                PlayMode::Stop | PlayMode::End => {
+                    if let Err(e) = self.podcasts.track_stopped(self.guild, &data, state).await {
+                        warn!("failed to save podcast position: {e}");
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// A podcast episode, titled from the feed rather than the file's tags.
+pub struct EpisodeAudio {
+    audio: DirectAudio,
+    show: String,
+    title: String,
+}
+
+impl EpisodeAudio {
+    pub fn new(client: Client, url: String, show: String, title: String) -> Self {
+        EpisodeAudio {
+            audio: DirectAudio::new(client, url),
+            show,
+            title,
+        }
+    }
+}
+
+impl From<EpisodeAudio> for Input {
+    fn from(episode: EpisodeAudio) -> Self {
+        Input::Lazy(Box::new(episode))
+    }
+}
+
+#[async_trait]
+impl Compose for EpisodeAudio {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.audio.create()
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        self.audio.create_async().await
+    }
+
+    fn should_create_async(&self) -> bool {
+        self.audio.should_create_async()
+    }
+
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        let metadata = self.audio.aux_metadata().await?;
+        Ok(AuxMetadata {
+            title: Some(self.title.clone()),
+            artist: Some(self.show.clone()),
+            album: Some(self.show.clone()),
+            ..metadata
+        })
+    }
+}
+
+impl Episode {
+    fn queueable(&self, show: &str) -> Queueable {
+        Queueable::Podcast {
+            url: self.url.clone(),
+            show: show.to_owned(),
+            title: self.title.clone(),
+        }
+    }
+}
+
+async fn autocomplete_show(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .podcasts
+        .subscriptions(ctx.guild_id().unwrap().into())
+        .into_iter()
+        .map(|s| s.title)
+        .filter(|title| title.to_lowercase().contains(&partial))
+        .take(25)
+        .collect()
+}
+
+/// Finds the subscription named `show`, or the only one if no name is given.
+async fn find_subscription(
+    ctx: Context<'_>,
+    show: Option<String>,
+) -> Result<Option<Subscription>, Error> {
+    let subscriptions = ctx
+        .data()
+        .podcasts
+        .subscriptions(ctx.guild_id().unwrap().into());
+    let found = match show {
+        Some(show) => subscriptions
+            .into_iter()
+            .find(|s| s.title.eq_ignore_ascii_case(&show) || s.url == show),
+        None if subscriptions.len() == 1 => subscriptions.into_iter().next(),
+        None if subscriptions.is_empty() => {
+            ctx.say("Not subscribed to any podcasts, use /podcast subscribe")
+                .await?;
+            return Ok(None);
+        }
+        None => {
+            ctx.say("Subscribed to several podcasts, pick one with the show option")
+                .await?;
+            return Ok(None);
+        }
+    };
+    if found.is_none() {
+        ctx.say("Not subscribed to that podcast").await?;
+    }
+    Ok(found)
+}
+
+#[poise::command(
+    slash_command,
+    guild_only,
+    category = "Podcasts",
+    subcommands("subscribe", "unsubscribe", "episodes", "podcast_play")
+)]
+/// Subscribe to podcasts and play their episodes
+pub async fn podcast(_ctx: Context<'_>) -> CommandResult {
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// Subscribe this server to a podcast's RSS or Atom feed
+async fn subscribe(
+    ctx: Context<'_>,
+    #[description = "URL of the feed"] feed: String,
+) -> CommandResult {
+    ctx.defer().await?;
+    let parsed = match fetch_feed(&ctx.data().client, &feed).await {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            ctx.say(format!("Could not read the feed: {e}")).await?;
+            return Ok(());
+        }
+    };
+    let title = if parsed.title.is_empty() {
+        feed.clone()
+    } else {
+        parsed.title
+    };
+    let subscription = Subscription {
+        url: feed,
+        title: title.clone(),
+    };
+    let guild = ctx.guild_id().unwrap().into();
+    if ctx.data().podcasts.subscribe(guild, subscription).await? {
+        ctx.say(format!(
+            "Subscribed to {title} ({} episodes)",
+            parsed.episodes.len()
+        ))
+        .await?;
+    } else {
+        ctx.say(format!("Already subscribed to {title}")).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// Unsubscribe this server from a podcast
+async fn unsubscribe(
+    ctx: Context<'_>,
+    #[description = "name of the podcast"]
+    #[autocomplete = "autocomplete_show"]
+    show: String,
+) -> CommandResult {
+    let Some(subscription) = find_subscription(ctx, Some(show)).await? else {
+        return Ok(());
+    };
+    let guild = ctx.guild_id().unwrap().into();
+    ctx.data()
+        .podcasts
+        .unsubscribe(guild, &subscription.url)
+        .await?;
+    ctx.say(format!("Unsubscribed from {}", subscription.title))
+        .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, guild_only)]
+/// List a podcast's latest episodes to pick from
+async fn episodes(
+    ctx: Context<'_>,
+    #[description = "name of the podcast"]
+    #[autocomplete = "autocomplete_show"]
+    show: Option<String>,
+    #[description = "number of episodes to display"] num: Option<usize>,
+) -> CommandResult {
+    if num.is_some_and(|x| x > 25) {
+        ctx.say("Number of episodes must be less than 25.").await?;
+        return Ok(());
+    }
+    let Some(subscription) = find_subscription(ctx, show).await? else {
+        return Ok(());
+    };
+    ctx.defer().await?;
+    let mut feed = fetch_feed(&ctx.data().client, &subscription.url).await?;
+    if feed.episodes.is_empty() {
+        ctx.say(format!("{} has no episodes", subscription.title))
+            .await?;
+        return Ok(());
+    }
+    feed.episodes.truncate(num.unwrap_or(10));
+
+    let guild = ctx.guild_id().unwrap().into();
+    let mut embed = CreateEmbed::default().title(format!("Episodes of {}", subscription.title));
+    for (i, episode) in feed.episodes.iter().enumerate() {
+        let mut details = vec![
+            episode.published.clone().unwrap_or("unknown date".into()),
+            episode
+                .duration
+                .map_or("unknown length".into(), format_duration),
+        ];
+        if let Some(position) = ctx.data().podcasts.position(guild, &episode.url) {
+            details.push(format!("resumes at {}", format_duration(position)));
+        }
+        embed = embed.field(
+            format!("{}: {}", i + 1, episode.title),
+            details.join(", "),
+            false,
+        );
+    }
+    let choices = feed
+        .episodes
+        .iter()
+        .map(|e| (e.title.clone(), vec![e.queueable(&subscription.title)]))
+        .collect();
+    offer_choices(ctx, embed, choices).await
+}
+
+#[poise::command(slash_command, guild_only, rename = "play")]
+/// Play a podcast episode, resuming where it was stopped
+async fn podcast_play(
+    ctx: Context<'_>,
+    #[description = "name of the podcast"]
+    #[autocomplete = "autocomplete_show"]
+    show: Option<String>,
+    #[description = "episode number from /podcast episodes, the latest by default"]
+    #[min = 1]
+    episode: Option<usize>,
+) -> CommandResult {
+    let Some(subscription) = find_subscription(ctx, show).await? else {
+        return Ok(());
+    };
+    ctx.defer().await?;
+    let feed = fetch_feed(&ctx.data().client, &subscription.url).await?;
+    let Some(episode) = feed.episodes.get(episode.unwrap_or(1) - 1) else {
+        ctx.say(format!(
+            "{} only has {} episodes",
+            subscription.title,
+            feed.episodes.len()
+        ))
+        .await?;
+        return Ok(());
+    };
+    let q = episode.queueable(&subscription.title);
+    enter_vc(ctx, true, |handler, ctx| async move {
+        play_multiple(ctx, vec![q], &handler).await
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("3600"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration(" 59:00 "), Some(Duration::from_secs(3540)));
+        assert_eq!(parse_duration("1:00:00"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1:xx"), None);
+        assert_eq!(parse_duration("18446744073709551615:00"), None);
+    }
+}
@@ -1,28 +1,42 @@
 use std::collections::VecDeque;
+use std::fmt;
 
 use rand::seq::SliceRandom;
+use songbird::error::ControlError;
 use songbird::tracks::Queued;
 
 use crate::metadata::format_metadata;
 use crate::track::TrackData;
 use crate::vc::enter_vc;
+use crate::ws::ServerMessage;
 use crate::{CommandResult, Context};
 
 crate::commands!(skip, r#move, swap, remove, clear, shuffle);
 
-async fn queue_modify<F: FnOnce(&mut VecDeque<Queued>) -> String>(
+/// Edits the queue with `f`, telling clients about it unless it failed.
+async fn queue_modify<F: FnOnce(&mut VecDeque<Queued>) -> Result<(), QueueError>>(
     ctx: Context<'_>,
     f: F,
 ) -> CommandResult {
     enter_vc(ctx, false, |handler_lock, ctx| async move {
         let handler = handler_lock.lock().await;
-        let m = handler.queue().modify_queue(f);
-        ctx.say(&m).await?;
+        let result = handler.queue().modify_queue(f);
+        drop(handler);
+        if result.is_ok() {
+            queue_changed(ctx);
+        }
+        ctx.say(success(result)).await?;
         Ok(())
     })
     .await
 }
 
+fn queue_changed(ctx: Context<'_>) {
+    ctx.data()
+        .events
+        .emit(ctx.guild_id().unwrap(), ServerMessage::QueueChanged);
+}
+
 #[poise::command(slash_command, category = "Queue")]
 /// Skip the current playing song in queue
 async fn skip(ctx: Context<'_>) -> CommandResult {
@@ -32,6 +46,8 @@ async fn skip(ctx: Context<'_>) -> CommandResult {
             ctx.say("queue is empty").await?;
         } else {
             let _ = handler.queue().skip();
+            drop(handler);
+            queue_changed(ctx);
             ctx.say("skipped").await?;
         }
         Ok(())
@@ -39,6 +55,76 @@ async fn skip(ctx: Context<'_>) -> CommandResult {
     .await
 }
 
+/// Why a track couldn't be moved, swapped or removed.
+#[derive(Debug)]
+pub enum QueueError {
+    /// The current song stays where it is; the action is named for the message.
+    Current(&'static str),
+    NoTrack(usize),
+    Stop(ControlError),
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueError::Current(action) => write!(f, "Cannot {action} the current song"),
+            QueueError::NoTrack(index) => write!(f, "No track at index {index}"),
+            QueueError::Stop(e) => write!(f, "Failed to stop track: {e:?}"),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+/// Moves the track at `from` to `to`, or to the end if `to` is past it.
+pub fn move_track(queue: &mut VecDeque<Queued>, from: usize, to: usize) -> Result<(), QueueError> {
+    if from == 0 || to == 0 {
+        return Err(QueueError::Current("move"));
+    }
+    let song = queue.remove(from).ok_or(QueueError::NoTrack(from))?;
+    if to > queue.len() {
+        queue.push_back(song);
+    } else {
+        queue.insert(to, song);
+    }
+    Ok(())
+}
+
+pub fn swap_tracks(queue: &mut VecDeque<Queued>, a: usize, b: usize) -> Result<(), QueueError> {
+    if a == 0 || b == 0 {
+        Err(QueueError::Current("swap"))
+    } else if a >= queue.len() {
+        Err(QueueError::NoTrack(a))
+    } else if b >= queue.len() {
+        Err(QueueError::NoTrack(b))
+    } else {
+        queue.swap(a, b);
+        Ok(())
+    }
+}
+
+/// Shuffles everything but the current track.
+pub fn shuffle_queue(queue: &mut VecDeque<Queued>) {
+    let slice = queue.make_contiguous();
+    if let Some(rest) = slice.get_mut(1..) {
+        rest.shuffle(&mut rand::rng());
+    }
+}
+
+/// Removes and stops the track at `index`.
+pub fn remove_track(queue: &mut VecDeque<Queued>, index: usize) -> Result<Queued, QueueError> {
+    if index == 0 {
+        return Err(QueueError::Current("remove"));
+    }
+    let track = queue.remove(index).ok_or(QueueError::NoTrack(index))?;
+    track.stop().map_err(QueueError::Stop)?;
+    Ok(track)
+}
+
+fn success(result: Result<(), QueueError>) -> String {
+    result.map_or_else(|e| e.to_string(), |()| "Success".into())
+}
+
 #[poise::command(slash_command, category = "Queue")]
 /// Reorder a track in the queue
 async fn r#move(
@@ -46,23 +132,7 @@ async fn r#move(
     #[description = "move from where"] from: usize,
     #[description = "move to where"] to: usize,
 ) -> CommandResult {
-    if from == 0 || to == 0 {
-        ctx.say("Cannot move the current song").await?;
-        return Ok(());
-    }
-    queue_modify(ctx, |x| {
-        if let Some(song) = x.remove(from) {
-            if to > x.len() {
-                x.push_back(song);
-            } else {
-                x.insert(to, song);
-            }
-            "Success".into()
-        } else {
-            format!("Failed: index out of bounds for {from}")
-        }
-    })
-    .await
+    queue_modify(ctx, |x| move_track(x, from, to)).await
 }
 
 #[poise::command(slash_command, category = "Queue")]
@@ -72,21 +142,7 @@ async fn swap(
     #[description = "swap from"] a: usize,
     #[description = "swap to"] b: usize,
 ) -> CommandResult {
-    if a == 0 || b == 0 {
-        ctx.say("Cannot swap the current song").await?;
-        return Ok(());
-    }
-    queue_modify(ctx, |x| {
-        if a >= x.len() {
-            format!("F: index out of bounds for {a}")
-        } else if b >= x.len() {
-            format!("F: index out of bounds for {b}")
-        } else {
-            x.swap(a, b);
-            "Success".into()
-        }
-    })
-    .await
+    queue_modify(ctx, |x| swap_tracks(x, a, b)).await
 }
 
 #[poise::command(slash_command, category = "Queue")]
@@ -97,6 +153,7 @@ async fn clear(ctx: Context<'_>) -> CommandResult {
         Ok(())
     })
     .await?;
+    queue_changed(ctx);
     ctx.say("cleared queue").await?;
     Ok(())
 }
@@ -105,9 +162,8 @@ async fn clear(ctx: Context<'_>) -> CommandResult {
 /// Shuffle queued tracks.
 async fn shuffle(ctx: Context<'_>) -> CommandResult {
     queue_modify(ctx, |x| {
-        let slice = x.make_contiguous();
-        slice[1..].shuffle(&mut rand::rng());
-        "Success".into()
+        shuffle_queue(x);
+        Ok(())
     })
     .await
 }
@@ -119,36 +175,22 @@ async fn remove(
     #[description = "which index to remove"] index: usize,
 ) -> CommandResult {
     enter_vc(ctx, false, |handler, ctx| async move {
-        if index == 0 {
-            ctx.say("Cannot remove the current song").await?;
-            return Ok(());
-        }
-
         let handler = handler.lock().await;
 
-        let result = handler.queue().modify_queue(|x| {
-            if let Some(track) = x.remove(index) {
-                if let Err(e) = track.stop() {
-                    Err(format!("Failed to stop track: {:?}", e))
-                } else {
-                    Ok(track)
-                }
-            } else {
-                Err(format!("No track at index {index}"))
-            }
-        });
+        let result = handler.queue().modify_queue(|x| remove_track(x, index));
 
         drop(handler);
 
         match result {
             Ok(track) => {
+                queue_changed(ctx);
                 let map = track.data::<TrackData>();
-                let metadata = &map.metadata;
-                ctx.say(&format!("Removed: {}", format_metadata(metadata)))
+                let metadata = map.metadata();
+                ctx.say(&format!("Removed: {}", format_metadata(&metadata)))
                     .await?;
             }
             Err(e) => {
-                ctx.say(&e).await?;
+                ctx.say(e.to_string()).await?;
             }
         }
 
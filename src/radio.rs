@@ -0,0 +1,338 @@
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::sync::Weak;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use color_eyre::eyre::{bail, eyre};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Response};
+use songbird::id::GuildId;
+use songbird::input::{
+    AsyncAdapterStream, AsyncMediaSource, AudioStream, AudioStreamError, AuxMetadata, Compose,
+    Input,
+};
+use symphonia::core::io::MediaSource;
+use symphonia::core::probe::Hint;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWriteExt, DuplexStream, ReadBuf};
+use tracing::debug;
+
+use crate::play::{play_common, Queueable};
+use crate::track::TrackData;
+use crate::ws::{Events, ServerMessage};
+use crate::{CommandResult, Context as PoiseContext, Error};
+
+crate::commands!(radio);
+
+/// Playlists may point at other playlists, but not forever.
+const MAX_PLAYLIST_DEPTH: usize = 3;
+
+fn is_playlist(response: &Response) -> bool {
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ty| ty.to_str().ok())
+        .unwrap_or_default();
+    let path = response.url().path().to_lowercase();
+    content_type.contains("mpegurl")
+        || content_type.contains("scpls")
+        || content_type.contains("pls+xml")
+        || path.ends_with(".m3u")
+        || path.ends_with(".m3u8")
+        || path.ends_with(".pls")
+}
+
+/// Finds the first stream in an M3U or PLS playlist.
+fn parse_playlist(text: &str) -> Option<String> {
+    let lines = text.lines().map(str::trim);
+    if text.trim_start().starts_with("[playlist]") {
+        // PLS: File1=http://...
+        lines
+            .filter_map(|line| line.split_once('='))
+            .find(|(key, _)| key.to_lowercase().starts_with("file"))
+            .map(|(_, url)| url.to_owned())
+    } else {
+        lines
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .next()
+    }
+}
+
+/// Requests the station's stream, following playlists, and asks for ICY metadata.
+async fn connect(client: &Client, url: &str) -> Result<Response, Error> {
+    let mut url = url.to_owned();
+    for _ in 0..MAX_PLAYLIST_DEPTH {
+        let response = client
+            .get(&url)
+            .header("Icy-MetaData", "1")
+            .send()
+            .await?
+            .error_for_status()?;
+        if !is_playlist(&response) {
+            return Ok(response);
+        }
+        url = parse_playlist(&response.text().await?).ok_or_else(|| eyre!("empty playlist"))?;
+    }
+    bail!("too many nested playlists")
+}
+
+fn header(response: &Response, name: &str) -> Option<String> {
+    let value = response.headers().get(name)?.to_str().ok()?;
+    Some(value.to_owned()).filter(|v| !v.is_empty())
+}
+
+/// Reads the song title from an ICY metadata block like `StreamTitle='Artist - Title';`.
+fn parse_stream_title(block: &[u8]) -> Option<String> {
+    let block = String::from_utf8_lossy(block);
+    let start = block.find("StreamTitle='")? + "StreamTitle='".len();
+    let len = block[start..].find("';")?;
+    Some(block[start..start + len].trim().to_owned()).filter(|title| !title.is_empty())
+}
+
+/// Where a station's song changes are shown.
+struct Listeners {
+    events: Events,
+    guild: GuildId,
+    track: Weak<TrackData>,
+}
+
+impl Listeners {
+    fn set_stream_title(&self, stream_title: String) {
+        let Some(track) = self.track.upgrade() else {
+            return;
+        };
+        {
+            let mut metadata = track.metadata.write().unwrap();
+            match stream_title.split_once(" - ") {
+                Some((artist, title)) => {
+                    metadata.artist = Some(artist.to_owned());
+                    metadata.title = Some(title.to_owned());
+                }
+                None => {
+                    metadata.artist = metadata.channel.clone();
+                    metadata.title = Some(stream_title);
+                }
+            }
+        }
+        // the station moving on to the next song is like a new track starting
+        self.events
+            .emit(self.guild, ServerMessage::TrackStarted(track.info()));
+    }
+}
+
+/// Copies the audio in `response` to `out`, taking out the metadata blocks
+/// sent every `metaint` bytes.
+async fn demux(
+    mut response: Response,
+    metaint: Option<usize>,
+    mut out: DuplexStream,
+    listeners: Listeners,
+) {
+    let mut until_meta = metaint.unwrap_or(usize::MAX);
+    // remaining length and contents of the metadata block being read
+    let mut meta: Option<(usize, Vec<u8>)> = None;
+    while let Ok(Some(chunk)) = response.chunk().await {
+        let mut chunk = &chunk[..];
+        while !chunk.is_empty() {
+            if let Some((len, block)) = &mut meta {
+                let n = (*len - block.len()).min(chunk.len());
+                block.extend_from_slice(&chunk[..n]);
+                chunk = &chunk[n..];
+                if block.len() == *len {
+                    if let Some(title) = parse_stream_title(block) {
+                        debug!(title, "radio stream title changed");
+                        listeners.set_stream_title(title);
+                    }
+                    meta = None;
+                    until_meta = metaint.unwrap();
+                }
+            } else if until_meta == 0 {
+                let len = chunk[0] as usize * 16;
+                chunk = &chunk[1..];
+                if len == 0 {
+                    until_meta = metaint.unwrap();
+                } else {
+                    meta = Some((len, Vec::with_capacity(len)));
+                }
+            } else {
+                let n = until_meta.min(chunk.len());
+                if out.write_all(&chunk[..n]).await.is_err() {
+                    // the track was stopped
+                    return;
+                }
+                until_meta -= n;
+                chunk = &chunk[n..];
+            }
+        }
+    }
+}
+
+/// The audio half of a radio stream, which can't be seeked.
+struct RadioStream(DuplexStream);
+
+impl AsyncRead for RadioStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for RadioStream {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+#[async_trait]
+impl AsyncMediaSource for RadioStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    async fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// An internet radio station. The track's title follows the song the
+/// station is playing.
+pub struct Radio {
+    client: Client,
+    url: String,
+    events: Events,
+    guild: GuildId,
+    track: Weak<TrackData>,
+}
+
+impl Radio {
+    pub fn new(
+        client: Client,
+        url: String,
+        events: Events,
+        guild: GuildId,
+        track: Weak<TrackData>,
+    ) -> Self {
+        Radio {
+            client,
+            url,
+            events,
+            guild,
+            track,
+        }
+    }
+}
+
+impl From<Radio> for Input {
+    fn from(radio: Radio) -> Self {
+        Input::Lazy(Box::new(radio))
+    }
+}
+
+#[async_trait]
+impl Compose for Radio {
+    fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        Err(AudioStreamError::Unsupported)
+    }
+
+    async fn create_async(
+        &mut self,
+    ) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+        let response = connect(&self.client, &self.url)
+            .await
+            .map_err(|e| AudioStreamError::Fail(e.into()))?;
+        let metaint = header(&response, "icy-metaint")
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n > 0);
+        let mut hint = Hint::new();
+        if let Some(content_type) = header(&response, CONTENT_TYPE.as_str()) {
+            hint.mime_type(&content_type);
+        }
+        let (reader, writer) = tokio::io::duplex(64 * 1024);
+        let listeners = Listeners {
+            events: self.events.clone(),
+            guild: self.guild,
+            track: self.track.clone(),
+        };
+        tokio::spawn(demux(response, metaint, writer, listeners));
+        let stream = AsyncAdapterStream::new(Box::new(RadioStream(reader)), 64 * 1024);
+        Ok(AudioStream {
+            input: Box::new(stream),
+            hint: Some(hint),
+        })
+    }
+
+    fn should_create_async(&self) -> bool {
+        true
+    }
+
+    /// Names the track after the station until the first song title arrives.
+    async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+        let response = connect(&self.client, &self.url)
+            .await
+            .map_err(|e| AudioStreamError::Fail(e.into()))?;
+        let station = header(&response, "icy-name");
+        Ok(AuxMetadata {
+            title: station.clone().or_else(|| Some(self.url.clone())),
+            channel: station,
+            source_url: Some(self.url.clone()),
+            ..AuxMetadata::default()
+        })
+    }
+}
+
+#[poise::command(slash_command, category = "Enqueue")]
+/// Play an internet radio station.
+pub async fn radio(
+    ctx: PoiseContext<'_>,
+    #[description = "URL of the stream, or of an M3U or PLS playlist"] url: String,
+) -> CommandResult {
+    play_common(ctx, url, |_, url| Queueable::Radio { url }, true).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_playlist, parse_stream_title};
+
+    #[test]
+    fn finds_the_first_stream_in_playlists() {
+        let pls = "[playlist]\nNumberOfEntries=2\nfile1=http://a.example/live\nFile2=http://b.example/live\n";
+        assert_eq!(
+            parse_playlist(pls).as_deref(),
+            Some("http://a.example/live")
+        );
+        let pls = "  [playlist]\r\nFILE1=http://a.example/live\r\n";
+        assert_eq!(
+            parse_playlist(pls).as_deref(),
+            Some("http://a.example/live")
+        );
+        assert_eq!(parse_playlist("[playlist]\nNumberOfEntries=0\n"), None);
+
+        let m3u =
+            "#EXTM3U\n\n#EXTINF:-1,Station\n  http://a.example/live  \nhttp://b.example/live\n";
+        assert_eq!(
+            parse_playlist(m3u).as_deref(),
+            Some("http://a.example/live")
+        );
+        assert_eq!(parse_playlist("#EXTM3U\n# nothing here\n"), None);
+    }
+
+    #[test]
+    fn reads_stream_titles() {
+        assert_eq!(
+            parse_stream_title(b"StreamTitle='Guns N' Roses - Patience';StreamUrl='';\0\0")
+                .as_deref(),
+            Some("Guns N' Roses - Patience")
+        );
+        assert_eq!(parse_stream_title(b"StreamTitle='Artist - Title"), None);
+        assert_eq!(parse_stream_title(b"StreamTitle='  ';"), None);
+        assert_eq!(parse_stream_title(b"StreamUrl='http://a.example';"), None);
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::extractor::{Extractor, ExtractorError};
+use crate::play::Queueable;
+use crate::search::{search_youtube, Provider, SearchResult};
+use crate::{Data, Error};
+
+/// How many search results are considered for each query.
+const CANDIDATES: usize = 5;
+
+/// Words that mark a different version of a song than the one asked for.
+const UNWANTED: &[&str] = &[
+    "live",
+    "cover",
+    "reaction",
+    "remix",
+    "karaoke",
+    "instrumental",
+    "nightcore",
+    "sped up",
+    "slowed",
+    "8d",
+];
+
+fn words(s: &str) -> Vec<String> {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Whether `phrase` appears as whole words in `words`.
+fn contains_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase = phrase.split(' ').collect::<Vec<_>>();
+    words
+        .windows(phrase.len())
+        .any(|window| window.iter().zip(&phrase).all(|(a, b)| a == b))
+}
+
+/// How well `candidate` fits `query`; higher is better.
+fn score(
+    query: &[String],
+    expected: Option<Duration>,
+    candidate: &SearchResult,
+    rank: usize,
+) -> f64 {
+    let title = words(candidate.title.as_deref().unwrap_or_default());
+    let channel = candidate.artist.as_deref().unwrap_or_default();
+    let text = [title.clone(), words(channel)].concat();
+
+    // providers already rank by relevance, so earlier results start ahead
+    let mut score = -(rank as f64);
+    let matched = query.iter().filter(|w| text.contains(w)).count();
+    score += 4.0 * matched as f64 / query.len().max(1) as f64;
+
+    if channel.ends_with(" - Topic") {
+        // channels YouTube generates for a label's releases only have the studio versions
+        score += 4.0;
+    }
+    if contains_phrase(&title, "official audio") {
+        score += 4.0;
+    } else if contains_phrase(&title, "official") {
+        score += 1.0;
+    }
+    for unwanted in UNWANTED {
+        if contains_phrase(&title, unwanted) && !contains_phrase(query, unwanted) {
+            score -= 5.0;
+        }
+    }
+
+    if let (Some(expected), Some(actual)) = (expected, candidate.duration) {
+        let off = (expected.as_secs_f64() - actual.as_secs_f64()).abs();
+        score += match off {
+            _ if off <= 3.0 => 6.0,
+            _ if off <= 10.0 => 3.0,
+            _ => -(off / 30.0).min(6.0),
+        };
+    }
+    score
+}
+
+/// Picks the result that most likely is the song asked for, rather than
+/// just the first one. `expected` is the song's length, when it is known.
+pub fn pick_best(
+    results: Vec<SearchResult>,
+    query: &str,
+    expected: Option<Duration>,
+) -> Option<SearchResult> {
+    let query_words = words(query);
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(rank, result)| (score(&query_words, expected, &result, rank), result))
+        .inspect(|(score, result)| debug!(score, ?result.title, "search candidate"))
+        // the earlier result wins a tie
+        .reduce(|best, next| if next.0 > best.0 { next } else { best })
+        .map(|(_, result)| result)
+}
+
+/// Searches `provider` for `query` and returns what to enqueue for the best match.
+pub async fn best_match(
+    provider: Provider,
+    data: &Data,
+    query: &str,
+    expected: Option<Duration>,
+) -> Result<Option<Queueable>, Error> {
+    let results = provider.search(data, query, CANDIDATES).await?;
+    Ok(pick_best(results, query, expected).map(|result| provider.queueable(result.url)))
+}
+
+/// Swaps a `ytsearch1:` term sent by a remote client for the best match.
+pub async fn resolve_search(extractor: &dyn Extractor, q: Queueable) -> Result<Queueable, Error> {
+    let Queueable::Ytdl { arg } = &q else {
+        return Ok(q);
+    };
+    let Some(query) = arg.strip_prefix("ytsearch1:") else {
+        return Ok(q);
+    };
+    let results = search_youtube(extractor, query, CANDIDATES).await?;
+    let found = pick_best(results, query, None).ok_or(ExtractorError::NoResults)?;
+    Ok(Queueable::Ytdl { arg: found.url })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractor::fake::{video, Scripted};
+
+    fn result(title: &str, artist: &str, seconds: Option<u64>) -> SearchResult {
+        SearchResult {
+            artist: Some(artist.into()),
+            title: Some(title.into()),
+            url: format!("https://youtu.be/{}", title.replace(' ', "_")),
+            duration: seconds.map(Duration::from_secs),
+        }
+    }
+
+    fn best(results: Vec<SearchResult>, query: &str, expected: Option<u64>) -> Option<String> {
+        pick_best(results, query, expected.map(Duration::from_secs)).and_then(|r| r.title)
+    }
+
+    #[test]
+    fn prefers_studio_versions() {
+        let results = vec![
+            result("Song (Live at Wembley)", "Band", None),
+            result("Song", "Band - Topic", None),
+        ];
+        assert_eq!(best(results, "band song", None).as_deref(), Some("Song"));
+
+        let results = vec![
+            result("Song cover", "Someone", None),
+            result("Song (Official Audio)", "Band", None),
+        ];
+        assert_eq!(
+            best(results, "band song", None).as_deref(),
+            Some("Song (Official Audio)")
+        );
+    }
+
+    #[test]
+    fn keeps_versions_the_query_asks_for() {
+        let results = vec![
+            result("Song", "Band", None),
+            result("Song (Live)", "Band", None),
+        ];
+        assert_eq!(
+            best(results, "band song live", None).as_deref(),
+            Some("Song (Live)")
+        );
+    }
+
+    #[test]
+    fn prefers_the_expected_length() {
+        let results = vec![
+            result("Song video", "Band", Some(400)),
+            result("Song", "Band", Some(201)),
+        ];
+        assert_eq!(
+            best(results, "band song", Some(200)).as_deref(),
+            Some("Song")
+        );
+    }
+
+    #[test]
+    fn earlier_results_win_ties() {
+        let results = vec![
+            result("Song a", "Band", None),
+            result("Song b", "Band", None),
+        ];
+        assert_eq!(best(results, "band song", None).as_deref(), Some("Song a"));
+        assert_eq!(best(Vec::new(), "band song", None), None);
+    }
+
+    #[tokio::test]
+    async fn remote_searches_pick_the_best_match() {
+        let mut topic = video("https://youtu.be/topic", "Song");
+        topic.channel = Some("Band - Topic".into());
+        let extractor = Scripted::default().with(
+            "ytsearch5:band song",
+            vec![video("https://youtu.be/live", "Song (Live)"), topic],
+        );
+        let q = Queueable::Ytdl {
+            arg: "ytsearch1:band song".into(),
+        };
+        match resolve_search(&extractor, q).await.unwrap() {
+            Queueable::Ytdl { arg } => assert_eq!(arg, "https://youtu.be/topic"),
+            q => panic!("resolved to {q:?}"),
+        }
+
+        let url = Queueable::Ytdl {
+            arg: "https://youtu.be/x".into(),
+        };
+        assert!(matches!(
+            resolve_search(&extractor, url).await.unwrap(),
+            Queueable::Ytdl { arg } if arg == "https://youtu.be/x"
+        ));
+        assert_eq!(extractor.asked(), ["ytsearch5:band song"]);
+    }
+}
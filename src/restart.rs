@@ -3,6 +3,7 @@ use std::mem::take;
 use std::num::NonZeroU64;
 
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 use crate::play::Queueable;
 use crate::track::TrackData;
@@ -46,6 +47,14 @@ pub async fn restart(ctx: Context<'_>) -> CommandResult {
         let queue = handler.queue().modify_queue(take);
         let mut data = Vec::with_capacity(queue.len());
         for x in &queue {
+            // the process may exit before the stop event is handled
+            if let Ok(state) = x.get_info().await {
+                let data = x.data::<TrackData>();
+                let podcasts = &ctx.data().podcasts;
+                if let Err(e) = podcasts.track_stopped(guild, &data, &state).await {
+                    warn!("failed to save podcast position: {e}");
+                }
+            }
             x.stop()?;
             let q = x.data::<TrackData>().queueable.clone();
             data.push(q);
@@ -2,78 +2,214 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use poise::serenity_prelude::{
-    ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow,
-    CreateInteractionResponse, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
-    EditMessage, Message,
+    ComponentInteractionCollector, ComponentInteractionDataKind, CreateActionRow, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateSelectMenu, CreateSelectMenuKind,
+    CreateSelectMenuOption, EditMessage, Message,
 };
-use poise::CreateReply;
+use poise::{ChoiceParameter, CreateReply};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
 use serenity::prelude::Mutex;
 use songbird::Call;
-use tokio::process::Command;
 
 use tokio::time::timeout;
-use tracing::debug;
 
-use crate::play::{play_multiple, Output, Queueable};
+use crate::extractor::{Extractor, Output};
+use crate::library::Library;
+use crate::play::{in_line, play_multiple, Queueable};
 use crate::vc::enter_vc;
-use crate::{yt_dlp_extra_args, CommandResult, Context};
+use crate::{CommandResult, Context, Data, Error};
 
 crate::commands!(search);
 
-pub struct SearchResult {
-    artist: Option<String>,
-    title: Option<String>,
-    url: String,
+pub use lyrebird_protocol::SearchResult;
+
+/// Where `/search` and `/splay` look for songs.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ChoiceParameter,
+)]
+pub enum Provider {
+    #[default]
+    #[name = "YouTube"]
+    YouTube,
+    #[name = "YouTube Music"]
+    YouTubeMusic,
+    #[name = "SoundCloud"]
+    SoundCloud,
+    #[name = "Bandcamp"]
+    Bandcamp,
+    /// The local library, see `/library`.
+    #[name = "Library"]
+    Library,
 }
 
-impl SearchResult {
-    pub fn title_or_url(&self) -> &str {
-        self.title.as_deref().unwrap_or(&self.url)
+impl Provider {
+    /// Returns up to `num` playable results for `keyword`.
+    pub async fn search(
+        self,
+        data: &Data,
+        keyword: &str,
+        num: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        self.search_in(&*data.extractor, &data.client, &data.library, keyword, num)
+            .await
+    }
+
+    /// [`Provider::search`] with only the parts of [`Data`] it uses.
+    pub(crate) async fn search_in(
+        self,
+        extractor: &dyn Extractor,
+        client: &Client,
+        library: &Library,
+        keyword: &str,
+        num: usize,
+    ) -> Result<Vec<SearchResult>, Error> {
+        match self {
+            Provider::YouTube => search_youtube(extractor, keyword, num).await,
+            Provider::YouTubeMusic => {
+                let mut url = Url::parse("https://music.youtube.com/search")?;
+                url.query_pairs_mut().append_pair("q", keyword);
+                search_with(extractor, url.as_str(), num).await
+            }
+            Provider::SoundCloud => {
+                search_with(extractor, &format!("scsearch{num}:{keyword}"), num).await
+            }
+            Provider::Bandcamp => search_bandcamp(client, keyword, num).await,
+            Provider::Library => Ok(library
+                .search(keyword)
+                .into_iter()
+                .take(num)
+                .map(|entry| SearchResult {
+                    title: Some(entry.title()),
+                    artist: entry.tags.artist,
+                    url: entry.path.display().to_string(),
+                    duration: entry.tags.duration,
+                })
+                .collect()),
+        }
+    }
+
+    /// What to enqueue for a result this provider found.
+    pub fn queueable(self, url: String) -> Queueable {
+        match self {
+            Provider::Library => Queueable::File { path: url.into() },
+            _ => Queueable::Ytdl { arg: url },
+        }
     }
 }
 
+/// Searches YouTube for `keyword`, returning up to `num` playable results.
+pub async fn search_youtube(
+    extractor: &dyn Extractor,
+    keyword: &str,
+    num: usize,
+) -> Result<Vec<SearchResult>, Error> {
+    search_with(extractor, &format!("ytsearch{num}:{keyword}"), num).await
+}
+
+/// Lists up to `num` playable results of a search prefix or search page.
+async fn search_with(
+    extractor: &dyn Extractor,
+    query: &str,
+    num: usize,
+) -> Result<Vec<SearchResult>, Error> {
+    let results = extractor
+        .search(query, num)
+        .await?
+        .into_iter()
+        .filter(Output::is_playable)
+        .map(|out| SearchResult {
+            artist: out.channel.or(out.uploader),
+            title: out.title,
+            url: out.url,
+            duration: out.duration.map(Duration::from_secs_f64),
+        })
+        .collect();
+    Ok(results)
+}
+
+#[derive(Deserialize)]
+struct BandcampResponse {
+    auto: BandcampResults,
+}
+
+#[derive(Deserialize)]
+struct BandcampResults {
+    results: Vec<BandcampResult>,
+}
+
+#[derive(Deserialize)]
+struct BandcampResult {
+    name: String,
+    #[serde(default)]
+    band_name: Option<String>,
+    item_url_path: String,
+}
+
+/// yt-dlp can play Bandcamp tracks but not search for them, so this uses
+/// the search box on Bandcamp's site.
+async fn search_bandcamp(
+    client: &Client,
+    keyword: &str,
+    num: usize,
+) -> Result<Vec<SearchResult>, Error> {
+    let response = client
+        .post("https://bandcamp.com/api/bcsearch_public_api/1/autocomplete_elastic")
+        .json(&serde_json::json!({
+            "search_text": keyword,
+            // tracks only
+            "search_filter": "t",
+            "full_page": false,
+            "fan_id": null,
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<BandcampResponse>()
+        .await?;
+    Ok(response
+        .auto
+        .results
+        .into_iter()
+        .take(num)
+        .map(|result| SearchResult {
+            artist: result.band_name,
+            title: Some(result.name),
+            url: result.item_url_path,
+            duration: None,
+        })
+        .collect())
+}
+
 #[poise::command(slash_command, category = "Enqueue")]
 /// Returns a list of songs from a given search term.
 pub async fn search(
     ctx: Context<'_>,
     #[description = "search term to use"] keyword: String,
     #[description = "number of results to display"] num: Option<usize>,
+    #[description = "where to search, defaults to /searchprovider"] provider: Option<Provider>,
 ) -> CommandResult {
-    if num.map_or(false, |x| x > 25) {
+    if num.is_some_and(|x| x > 25) {
         ctx.say("Number of results must be less than 25.").await?;
         return Ok(());
     }
     let num = num.unwrap_or(5);
+    let provider = provider.unwrap_or_else(|| default_provider(ctx));
 
     ctx.defer().await?;
-    let cmd = Command::new("yt-dlp")
-        .args(yt_dlp_extra_args())
-        .arg("-j")
-        .arg("-s")
-        .arg("--flat-playlist")
-        .arg(format!("ytsearch{num}:{keyword}"))
-        .output()
+    let results = in_line(ctx, provider.search(ctx.data(), &keyword, num)).await?;
+    if results.is_empty() {
+        ctx.say(format!(
+            "No results for \"{keyword}\" on {}",
+            provider.name()
+        ))
         .await?;
-
-    let mut results = Vec::new();
-    for bytes in cmd.stdout.split(|x| *x == b'\n') {
-        if bytes.is_empty() {
-            continue;
-        }
-        let out = serde_json::from_slice::<Output>(bytes)?;
-        debug!(?out);
-        if !out.is_playable() {
-            continue;
-        }
-        results.push(SearchResult {
-            artist: out.channel,
-            title: out.title,
-            url: out.url,
-        });
+        return Ok(());
     }
 
-    let mut embed = serenity::builder::CreateEmbed::default()
-        .title(format!("Search results for \"{keyword}\""));
+    let mut embed = CreateEmbed::default()
+        .title(format!("Search results for \"{keyword}\""))
+        .footer(CreateEmbedFooter::new(format!("from {}", provider.name())));
     for (i, result) in results.iter().enumerate() {
         embed = embed.field(
             format!(
@@ -85,16 +221,36 @@ pub async fn search(
             false,
         );
     }
-    let options = results
+    let choices = results
+        .iter()
+        .map(|x| {
+            let q = provider.queueable(x.url.clone());
+            (x.title_or_url().to_owned(), vec![q])
+        })
+        .collect();
+    offer_choices(ctx, embed, choices).await
+}
+
+/// The provider the guild picked with `/searchprovider`.
+pub fn default_provider(ctx: Context<'_>) -> Provider {
+    ctx.guild_id().map_or_else(Provider::default, |guild| {
+        ctx.data().settings.get(guild.into()).search_provider
+    })
+}
+
+/// Shows `embed` with a select menu of `choices`, and enqueues what the
+/// user picks within a minute.
+pub async fn offer_choices(
+    ctx: Context<'_>,
+    embed: CreateEmbed,
+    choices: Vec<(String, Vec<Queueable>)>,
+) -> CommandResult {
+    let options = choices
         .iter()
         .enumerate()
-        .map(|(i, x)| {
+        .map(|(i, (label, _))| {
             CreateSelectMenuOption::new(
-                format!(
-                    "{} - {}",
-                    i + 1,
-                    x.title_or_url().chars().take(98).collect::<String>()
-                ),
+                format!("{} - {}", i + 1, label.chars().take(98).collect::<String>()),
                 i.to_string(),
             )
         })
@@ -116,7 +272,7 @@ pub async fn search(
     let collector = msg.await_component_interactions(ctx);
 
     enter_vc(ctx, true, move |handler, ctx| async move {
-        handle_search_responses(msg, ctx, handler, collector, results).await
+        handle_search_responses(msg, ctx, handler, collector, choices).await
     })
     .await?;
 
@@ -128,7 +284,7 @@ async fn handle_search_responses(
     ctx: Context<'_>,
     handler: Arc<Mutex<Call>>,
     rxns: ComponentInteractionCollector,
-    results: Vec<SearchResult>,
+    choices: Vec<(String, Vec<Queueable>)>,
 ) -> CommandResult {
     if let Ok(Some(interaction)) = timeout(Duration::from_secs(60), rxns.next()).await {
         interaction
@@ -153,14 +309,108 @@ async fn handle_search_responses(
 
             let inputs = values
                 .iter()
-                .map(|x| Queueable::Ytdl {
-                    arg: results[*x].url.clone(),
-                })
+                .flat_map(|x| choices[*x].1.clone())
                 .collect::<Vec<_>>();
 
-            play_multiple(ctx, inputs, &mut *handler.lock().await).await?;
+            play_multiple(ctx, inputs, &handler).await?;
         }
     }
     msg.edit(ctx, EditMessage::new().components(vec![])).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractor::fake::{playlist, video, Scripted};
+
+    #[tokio::test]
+    async fn search_keeps_playable_results() {
+        let mut with_channel = video("https://youtu.be/a", "A");
+        with_channel.channel = Some("Channel".into());
+        with_channel.uploader = Some("Uploader".into());
+        with_channel.duration = Some(90.0);
+        let mut with_uploader = video("https://soundcloud.com/b", "B");
+        with_uploader.uploader = Some("Uploader".into());
+        let extractor = Scripted::default().with(
+            "ytsearch3:song",
+            vec![
+                with_channel,
+                playlist("https://youtube.com/playlist?list=c"),
+                with_uploader,
+            ],
+        );
+
+        let results = search_with(&extractor, "ytsearch3:song", 3).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].artist.as_deref(), Some("Channel"));
+        assert_eq!(results[0].duration, Some(Duration::from_secs(90)));
+        assert_eq!(results[1].url, "https://soundcloud.com/b");
+        assert_eq!(results[1].artist.as_deref(), Some("Uploader"));
+    }
+
+    #[tokio::test]
+    async fn providers_search_through_the_extractor() {
+        let extractor = Scripted::default().with(
+            "scsearch2:some song",
+            vec![video("https://soundcloud.com/a", "A")],
+        );
+        let (client, library) = (Client::new(), Library::with_entries(Vec::new()));
+        for provider in [
+            Provider::YouTube,
+            Provider::YouTubeMusic,
+            Provider::SoundCloud,
+        ] {
+            provider
+                .search_in(&extractor, &client, &library, "some song", 2)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            extractor.asked(),
+            [
+                "ytsearch2:some song",
+                "https://music.youtube.com/search?q=some+song",
+                "scsearch2:some song",
+            ]
+        );
+
+        let results = Provider::SoundCloud
+            .search_in(&extractor, &client, &library, "some song", 2)
+            .await
+            .unwrap();
+        assert_eq!(results[0].title.as_deref(), Some("A"));
+        assert!(matches!(
+            Provider::SoundCloud.queueable(results[0].url.clone()),
+            Queueable::Ytdl { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn library_search_skips_the_extractor() {
+        let extractor = Scripted::default();
+        let results = Provider::Library
+            .search_in(
+                &extractor,
+                &Client::new(),
+                &Library::with_entries(Vec::new()),
+                "song",
+                5,
+            )
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+        assert!(extractor.asked().is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_failures_come_from_the_extractor() {
+        let extractor = Scripted::default()
+            .failing("ytsearch1:song", "ERROR: HTTP Error 429: Too Many Requests");
+        let error = search_youtube(&extractor, "song", 1).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The site is rate limiting the bot, try again later."
+        );
+    }
+}
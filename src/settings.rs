@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::env;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use poise::ChoiceParameter;
+use serde::{Deserialize, Serialize};
+use songbird::id::GuildId;
+use tokio::fs;
+
+use crate::search::Provider;
+use crate::{CommandResult, Context};
+
+crate::commands!(searchprovider);
+
+/// How a guild has set the bot up.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GuildSettings {
+    #[serde(default)]
+    pub search_provider: Provider,
+}
+
+/// Settings of each guild, persisted as JSON.
+pub struct Settings {
+    path: PathBuf,
+    guilds: Mutex<HashMap<NonZeroU64, GuildSettings>>,
+}
+
+impl Settings {
+    /// Loads settings from `SETTINGS_PATH`, defaulting to `./settings.json`.
+    pub async fn load() -> color_eyre::Result<Arc<Self>> {
+        let path = PathBuf::from(env::var("SETTINGS_PATH").unwrap_or("./settings.json".into()));
+        let guilds = match fs::read_to_string(&path).await {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Arc::new(Settings {
+            path,
+            guilds: Mutex::new(guilds),
+        }))
+    }
+
+    pub fn get(&self, guild: GuildId) -> GuildSettings {
+        self.guilds
+            .lock()
+            .unwrap()
+            .get(&guild.0)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn update(
+        &self,
+        guild: GuildId,
+        f: impl FnOnce(&mut GuildSettings),
+    ) -> color_eyre::Result<()> {
+        f(self.guilds.lock().unwrap().entry(guild.0).or_default());
+        self.save().await
+    }
+
+    async fn save(&self) -> color_eyre::Result<()> {
+        let s = serde_json::to_string_pretty(&*self.guilds.lock().unwrap())?;
+        fs::write(&self.path, s).await?;
+        Ok(())
+    }
+}
+
+/// Whether the author may change the server's settings.
+async fn can_manage_guild(ctx: Context<'_>) -> bool {
+    let Some(member) = ctx.author_member().await else {
+        return false;
+    };
+    ctx.guild()
+        .is_some_and(|g| g.member_permissions(&member).manage_guild())
+}
+
+#[poise::command(slash_command, guild_only, category = "Settings")]
+/// Show or change where /search and /splay look for songs by default
+pub async fn searchprovider(
+    ctx: Context<'_>,
+    #[description = "the new default"] provider: Option<Provider>,
+) -> CommandResult {
+    let guild = ctx.guild_id().unwrap().into();
+    let settings = &ctx.data().settings;
+    let Some(provider) = provider else {
+        let current = settings.get(guild).search_provider;
+        ctx.say(format!("Searching {} by default", current.name()))
+            .await?;
+        return Ok(());
+    };
+    // anyone can see the default, but changing it is up to the moderators
+    if !can_manage_guild(ctx).await {
+        ctx.say("Changing the default needs the Manage Server permission")
+            .await?;
+        return Ok(());
+    }
+    settings
+        .update(guild, |s| s.search_provider = provider)
+        .await?;
+    ctx.say(format!("Now searching {} by default", provider.name()))
+        .await?;
+    Ok(())
+}
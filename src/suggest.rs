@@ -0,0 +1,198 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude::{AutocompleteChoice, UserId};
+use tokio::spawn;
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout};
+use tracing::debug;
+
+use crate::extractor::{Budget, SEARCH_BUDGET};
+use crate::search::{default_provider, Provider};
+use crate::{Context, Data};
+
+/// How many played tracks are remembered for each user.
+const RECENT_PER_USER: usize = 20;
+
+/// Searches only start once the user stops typing for this long.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Discord drops autocomplete responses after three seconds.
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long search results are reused for the same text.
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Discord rejects longer choice names and values.
+const MAX_CHOICE_LEN: usize = 100;
+
+/// How many search results are suggested after the recent tracks.
+const LIVE_SUGGESTIONS: usize = 5;
+
+#[derive(Clone)]
+struct Suggestion {
+    title: String,
+    url: String,
+}
+
+impl Suggestion {
+    fn choice(&self) -> AutocompleteChoice {
+        let title = self.title.chars().take(MAX_CHOICE_LEN).collect::<String>();
+        AutocompleteChoice::new(title, self.url.clone())
+    }
+}
+
+/// The provider searched, and the lowercased text.
+type SearchKey = (Provider, String);
+
+/// When the search was made, and what it found.
+type CachedSearch = (Instant, Vec<Suggestion>);
+
+/// What a running search finds, once it is done.
+type Pending = watch::Receiver<Option<Vec<Suggestion>>>;
+
+/// What `/play` and `/splay` suggest while the user is typing.
+#[derive(Default)]
+pub struct Suggestions {
+    recent: Mutex<HashMap<UserId, VecDeque<Suggestion>>>,
+    /// Recent search results.
+    searches: Mutex<HashMap<SearchKey, CachedSearch>>,
+    /// Searches still running, which the same text typed again waits on.
+    running: Mutex<HashMap<SearchKey, Pending>>,
+    /// Counts each user's keystrokes, so stale ones can skip searching.
+    typed: Mutex<HashMap<UserId, u64>>,
+}
+
+impl Suggestions {
+    /// Remembers that `user` queued the track at `url`.
+    pub fn played(&self, user: UserId, title: String, url: String) {
+        // longer URLs can't be a choice's value
+        if !url.starts_with("http") || url.len() > MAX_CHOICE_LEN {
+            return;
+        }
+        let mut recent = self.recent.lock().unwrap();
+        let recent = recent.entry(user).or_default();
+        recent.retain(|s| s.url != url);
+        recent.push_front(Suggestion { title, url });
+        recent.truncate(RECENT_PER_USER);
+    }
+
+    fn recent(&self, user: UserId, partial: &str) -> Vec<Suggestion> {
+        let partial = partial.to_lowercase();
+        self.recent
+            .lock()
+            .unwrap()
+            .get(&user)
+            .into_iter()
+            .flatten()
+            .filter(|s| s.title.to_lowercase().contains(&partial))
+            .cloned()
+            .collect()
+    }
+
+    fn cached(&self, key: &SearchKey) -> Option<Vec<Suggestion>> {
+        let mut searches = self.searches.lock().unwrap();
+        searches.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+        searches.get(key).map(|(_, results)| results.clone())
+    }
+
+    /// Whether `user` typed something else while waiting for [`DEBOUNCE`].
+    async fn typed_again(&self, user: UserId) -> bool {
+        let keystroke = {
+            let mut typed = self.typed.lock().unwrap();
+            let count = typed.entry(user).or_default();
+            *count += 1;
+            *count
+        };
+        sleep(DEBOUNCE).await;
+        self.typed.lock().unwrap().get(&user) != Some(&keystroke)
+    }
+
+    /// Searches for `key` unless the same search is already running. It runs
+    /// on its own, so the results are cached even when Discord stopped
+    /// waiting for them.
+    fn search(self: &Arc<Self>, data: &Data, key: SearchKey) -> Pending {
+        let mut running = self.running.lock().unwrap();
+        if let Some(pending) = running.get(&key) {
+            return pending.clone();
+        }
+        let (tx, pending) = watch::channel(None);
+        running.insert(key.clone(), pending.clone());
+        let suggestions = self.clone();
+        let extractor = data.extractor.clone();
+        let client = data.client.clone();
+        let library = data.library.clone();
+        spawn(async move {
+            let (provider, text) = &key;
+            let search = provider.search_in(&*extractor, &client, &library, text, LIVE_SUGGESTIONS);
+            let results = SEARCH_BUDGET.scope(Budget::Suggestions, search).await;
+            let results = match results {
+                Ok(results) => results
+                    .into_iter()
+                    .filter(|r| r.url.len() <= MAX_CHOICE_LEN)
+                    .map(|r| Suggestion {
+                        title: r.title_or_url().to_owned(),
+                        url: r.url,
+                    })
+                    .collect::<Vec<_>>(),
+                Err(e) => {
+                    debug!(%e, "could not search for suggestions");
+                    suggestions.running.lock().unwrap().remove(&key);
+                    return;
+                }
+            };
+            suggestions
+                .searches
+                .lock()
+                .unwrap()
+                .insert(key.clone(), (Instant::now(), results.clone()));
+            suggestions.running.lock().unwrap().remove(&key);
+            tx.send_replace(Some(results));
+        });
+        pending
+    }
+}
+
+/// Search results for `partial`, from the cache if it was searched for recently.
+async fn live(ctx: Context<'_>, partial: &str) -> Vec<Suggestion> {
+    // library files aren't URLs, so suggest YouTube results instead
+    let provider = match default_provider(ctx) {
+        Provider::Library => Provider::YouTube,
+        provider => provider,
+    };
+    let suggestions = &ctx.data().suggestions;
+    let key = (provider, partial.trim().to_lowercase());
+    if let Some(results) = suggestions.cached(&key) {
+        return results;
+    }
+    if suggestions.typed_again(ctx.author().id).await {
+        return Vec::new();
+    }
+    let mut pending = suggestions.search(ctx.data(), key);
+    let results = match timeout(SEARCH_TIMEOUT, pending.wait_for(Option::is_some)).await {
+        Ok(Ok(results)) => results.clone().unwrap_or_default(),
+        // too slow this time, but the next keystroke may find it cached
+        _ => Vec::new(),
+    };
+    results
+}
+
+/// Suggests the user's recently played tracks, then search results, each
+/// labelled with its title and filled in as its URL.
+pub async fn autocomplete_track(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let mut suggestions = ctx.data().suggestions.recent(ctx.author().id, partial);
+    if !partial.trim().is_empty() && !partial.starts_with("http") {
+        suggestions.extend(live(ctx, partial).await);
+    }
+    let mut seen = Vec::new();
+    suggestions
+        .into_iter()
+        .filter(|s| {
+            let new = !seen.contains(&s.url);
+            seen.push(s.url.clone());
+            new
+        })
+        .map(|s| s.choice())
+        .collect()
+}
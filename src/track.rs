@@ -1,20 +1,78 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::RwLock;
 
+use songbird::error::ControlError;
 use songbird::input::AuxMetadata;
+use songbird::tracks::TrackHandle;
 use tracing::warn;
 
 use crate::play::Queueable;
 use crate::vc::enter_vc;
+use crate::ws::TrackInfo;
 use crate::{CommandResult, Context};
 
 crate::commands!(pause, resume, r#loop);
 
 pub struct TrackData {
-    pub metadata: AuxMetadata,
+    /// Identifies the track for remote clients while it is in the queue.
+    pub id: u32,
+    /// Can change while the track plays, like the song on a radio station.
+    pub metadata: RwLock<AuxMetadata>,
     pub queueable: Queueable,
     pub is_loop_enabled: AtomicBool,
 }
 
+impl TrackData {
+    pub fn new(queueable: Queueable) -> Self {
+        TrackData {
+            id: TrackData::next_id(),
+            metadata: RwLock::default(),
+            queueable,
+            is_loop_enabled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn metadata(&self) -> AuxMetadata {
+        self.metadata.read().unwrap().clone()
+    }
+
+    /// Whether the track is a stream without an end.
+    pub fn is_live(&self) -> bool {
+        matches!(self.queueable, Queueable::Radio { .. })
+    }
+
+    pub fn info(&self) -> TrackInfo {
+        let metadata = self.metadata();
+        TrackInfo {
+            title: metadata.title,
+            artist: metadata.artist,
+            duration: metadata.duration,
+            thumbnail: metadata.thumbnail,
+            live: self.is_live(),
+        }
+    }
+
+    pub fn next_id() -> u32 {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Flips loop mode for `track`, returning whether it is now enabled.
+pub fn toggle_loop(track: &TrackHandle) -> Result<bool, ControlError> {
+    let map = track.data::<TrackData>();
+
+    let was_previously_enabled = map.is_loop_enabled.fetch_not(Ordering::SeqCst);
+    drop(map);
+
+    if was_previously_enabled {
+        track.disable_loop()?;
+    } else {
+        track.enable_loop()?;
+    }
+    Ok(!was_previously_enabled)
+}
+
 #[poise::command(slash_command, category = "Controls")]
 /// Pause the current track
 async fn pause(ctx: Context<'_>) -> CommandResult {
@@ -58,17 +116,10 @@ async fn r#loop(ctx: Context<'_>) -> CommandResult {
             return Ok(());
         };
 
-        let map = current.data::<TrackData>();
-
-        let was_previously_enabled = map.is_loop_enabled.fetch_not(Ordering::SeqCst);
-        drop(map);
-
-        if was_previously_enabled {
-            current.disable_loop()?;
-            c.say("Looping disabled").await?;
-        } else {
-            current.enable_loop()?;
+        if toggle_loop(&current)? {
             c.say("Looping enabled").await?;
+        } else {
+            c.say("Looping disabled").await?;
         }
 
         Ok(())
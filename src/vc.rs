@@ -3,10 +3,17 @@ use std::sync::Arc;
 
 use serenity::async_trait;
 use serenity::prelude::Mutex;
+use songbird::error::JoinError;
+use songbird::events::context_data::{DisconnectData, DisconnectKind, DisconnectReason};
+use songbird::id::{ChannelId, GuildId};
+use songbird::model::CloseCode;
 use songbird::tracks::PlayMode;
-use songbird::{Call, EventContext};
+use songbird::{Call, CoreEvent, Event, EventContext, Songbird, TrackEvent};
 use tracing::warn;
 
+use crate::podcast::{Podcasts, PositionTracker};
+use crate::track::TrackData;
+use crate::ws::{Events, ServerMessage};
 use crate::{CommandResult, Context};
 
 crate::commands!(deafen, undeafen, join, leave);
@@ -27,6 +34,126 @@ impl songbird::EventHandler for ErrorHandler {
     }
 }
 
+/// Forwards track and connection events from songbird to WebSocket clients.
+pub struct EventForwarder {
+    guild: GuildId,
+    events: Events,
+}
+
+#[async_trait]
+impl songbird::EventHandler for EventForwarder {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<songbird::Event> {
+        let e = match ctx {
+            EventContext::Track(e) => e,
+            EventContext::DriverDisconnect(data) => {
+                if is_final(data) {
+                    self.events.emit(self.guild, ServerMessage::Left);
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        for (state, handle) in *e {
+            let track = handle.data::<TrackData>().info();
+            let message = match &state.playing {
+                // resuming a paused track also fires `TrackEvent::Play`
+                PlayMode::Play if state.play_time.is_zero() => ServerMessage::TrackStarted(track),
+                PlayMode::Play => ServerMessage::Resumed,
+                PlayMode::Pause => ServerMessage::Paused,
+                PlayMode::Stop | PlayMode::End => ServerMessage::TrackEnded(track),
+                PlayMode::Errored(e) => ServerMessage::TrackErrored {
+                    track,
+                    error: e.to_string(),
+                },
+                _ => continue,
+            };
+            self.events.emit(self.guild, message);
+        }
+        None
+    }
+}
+
+/// Whether the bot is out of the voice channel for good after `data`: it left
+/// with `/leave`, was kicked, or songbird gave up reconnecting. Other dropped
+/// connections are kept to reconnect once Discord hands out a new voice server.
+fn is_final(data: &DisconnectData<'_>) -> bool {
+    match (data.kind, data.reason) {
+        (_, None | Some(DisconnectReason::Requested)) => true,
+        // a newer attempt, like joining another channel, took its place
+        (_, Some(DisconnectReason::AttemptDiscarded)) => false,
+        (DisconnectKind::Runtime, Some(DisconnectReason::WsClosed(code))) => {
+            code == Some(CloseCode::Disconnected)
+        }
+        (DisconnectKind::Runtime, _) => false,
+        _ => true,
+    }
+}
+
+/// Registers the global event handlers every call should have.
+pub fn add_global_events(
+    call: &mut Call,
+    guild: GuildId,
+    events: &Events,
+    podcasts: &Arc<Podcasts>,
+) {
+    call.add_global_event(Event::Track(TrackEvent::Error), ErrorHandler);
+    for event in [
+        TrackEvent::Play,
+        TrackEvent::Pause,
+        TrackEvent::End,
+        TrackEvent::Error,
+    ] {
+        call.add_global_event(
+            Event::Track(event),
+            EventForwarder {
+                guild,
+                events: events.clone(),
+            },
+        );
+    }
+    call.add_global_event(
+        Event::Core(CoreEvent::DriverDisconnect),
+        EventForwarder {
+            guild,
+            events: events.clone(),
+        },
+    );
+    for event in [TrackEvent::Play, TrackEvent::End] {
+        call.add_global_event(
+            Event::Track(event),
+            PositionTracker {
+                guild,
+                podcasts: podcasts.clone(),
+            },
+        );
+    }
+}
+
+/// Joins `channel` and sets the call up the way every command expects.
+pub async fn join_channel(
+    manager: &Songbird,
+    guild: GuildId,
+    channel: ChannelId,
+    events: &Events,
+    podcasts: &Arc<Podcasts>,
+) -> Result<Arc<Mutex<Call>>, &'static str> {
+    let handler = manager
+        .join(guild, channel)
+        .await
+        .map_err(|_x| "songbird error")?;
+
+    add_global_events(&mut *handler.lock().await, guild, events, podcasts);
+    events.emit(guild, ServerMessage::Joined { channel: channel.0 });
+
+    Ok(handler)
+}
+
+/// Leaves the voice channel in `guild`, if there is one. Clients are told by
+/// the [`EventForwarder`] once the connection is gone.
+pub async fn leave_channel(manager: &Songbird, guild: GuildId) -> Result<(), JoinError> {
+    manager.remove(guild).await
+}
+
 pub async fn try_join(ctx: Context<'_>, must_join: bool) -> Result<Arc<Mutex<Call>>, &'static str> {
     let guild = ctx.guild_id().unwrap();
     let user = ctx.author().id;
@@ -57,15 +184,14 @@ pub async fn try_join(ctx: Context<'_>, must_join: bool) -> Result<Arc<Mutex<Cal
         }
     };
 
-    let handler = manager
-        .join(guild, connect_to)
-        .await
-        .map_err(|_x| "songbird error")?;
-
-    handler.lock().await.add_global_event(
-        songbird::Event::Track(songbird::TrackEvent::Error),
-        ErrorHandler,
-    );
+    let handler = join_channel(
+        &manager,
+        guild.into(),
+        connect_to.into(),
+        &ctx.data().events,
+        &ctx.data().podcasts,
+    )
+    .await?;
 
     // TODO: event handlers for play announcement
     // h.lock().await.add_global_event(Event::Track(TrackEvent::Play), action);
@@ -168,7 +294,7 @@ async fn leave(ctx: Context<'_>) -> CommandResult {
 
     if has_handler {
         // TODO replace with let chains
-        if let Err(e) = manager.remove(guild_id).await {
+        if let Err(e) = leave_channel(&manager, guild_id.into()).await {
             ctx.say(format!("Failed: {e:?}")).await?;
         } else {
             ctx.say("Left voice channel").await?;
@@ -1,49 +1,317 @@
-// TODO remove this once WS is ready
-#![allow(dead_code)]
-
+use std::collections::HashSet;
+use std::fmt;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
-
-use color_eyre::eyre::ContextCompat;
-use serde::{Deserialize, Serialize};
-use songbird::Call;
-
-use crate::CommandResult;
-
-/// A message that is sent to the server to control musicbot.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ClientMessage {
-    /// Stops the current song and clears the queue.
-    ClearAll,
-    /// Pause current playback
-    Pause,
-    /// Resume current playback
-    Resume,
-    /// Skip the current song.
-    Skip,
-    /// Seek to a position in the current song.
-    Seek(Duration),
-}
-
-async fn handle(call: Arc<tokio::sync::Mutex<Call>>, message: ClientMessage) -> CommandResult {
-    use ClientMessage::*;
-    match message {
-        ClearAll => call.lock().await.queue().stop(),
-        Pause => call.lock().await.queue().pause()?,
-        Resume => call.lock().await.queue().resume()?,
-        Skip => call.lock().await.queue().skip()?,
-        Seek(duration) => {
-            call.lock()
+
+use color_eyre::eyre::{bail, eyre, ContextCompat};
+use poise::futures_util::{SinkExt, StreamExt};
+use songbird::id::{ChannelId, GuildId};
+use songbird::Songbird;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request as Handshake};
+use tokio_tungstenite::tungstenite::http::{HeaderMap, StatusCode, Uri};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+
+use crate::auth::{required_scope, Scope, Token, TokenStore};
+use crate::list::snapshot;
+use crate::play::{enqueue, Sources};
+use crate::podcast::Podcasts;
+use crate::queue::{move_track, remove_track, shuffle_queue, swap_tracks};
+use crate::resolve::resolve_search;
+use crate::search::search_youtube;
+use crate::track::toggle_loop;
+use crate::vc::{join_channel, leave_channel};
+use crate::Error;
+
+pub use lyrebird_protocol::{
+    ClientMessage, QueueEntry, QueueSnapshot, Request, Response, ServerMessage, TrackInfo,
+};
+
+/// Broadcasts [`ServerMessage`]s to every connected client.
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<(GuildId, ServerMessage)>);
+
+impl Events {
+    pub fn new() -> Self {
+        Events(broadcast::channel(64).0)
+    }
+
+    pub fn emit(&self, guild: impl Into<GuildId>, message: ServerMessage) {
+        // no receivers just means no clients are connected
+        let _ = self.0.send((guild.into(), message));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(GuildId, ServerMessage)> {
+        self.0.subscribe()
+    }
+}
+
+/// Why a request can't be carried out, besides yt-dlp, songbird or the queue
+/// refusing it.
+#[derive(Debug)]
+pub enum RemoteError {
+    /// The request makes no sense on its own.
+    Invalid(&'static str),
+    /// The request doesn't fit what the bot is doing right now.
+    Conflict(&'static str),
+}
+
+const NOTHING_PLAYING: RemoteError = RemoteError::Conflict("no current song");
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::Invalid(e) | RemoteError::Conflict(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// What remote control clients act on, shared by the WebSocket and HTTP servers.
+pub struct Remote {
+    pub songbird: Arc<Songbird>,
+    pub sources: Sources,
+    pub events: Events,
+    pub tokens: Arc<TokenStore>,
+    pub podcasts: Arc<Podcasts>,
+}
+
+impl Remote {
+    pub fn new(
+        songbird: Arc<Songbird>,
+        sources: Sources,
+        events: Events,
+        tokens: Arc<TokenStore>,
+        podcasts: Arc<Podcasts>,
+    ) -> Self {
+        Self {
+            songbird,
+            sources,
+            events,
+            tokens,
+            podcasts,
+        }
+    }
+
+    /// Runs `message` against the call in `guild` on behalf of `token`.
+    pub async fn handle(
+        &self,
+        token: &Token,
+        guild: GuildId,
+        message: ClientMessage,
+    ) -> Result<Response, Error> {
+        use ClientMessage::*;
+        token.authorize(guild, required_scope(&message))?;
+        match message {
+            Join { channel } => {
+                if self.songbird.get(guild).is_some() {
+                    bail!(RemoteError::Conflict("already in a voice channel"));
+                }
+                join_channel(
+                    &self.songbird,
+                    guild,
+                    ChannelId(channel),
+                    &self.events,
+                    &self.podcasts,
+                )
                 .await
-                .queue()
-                .current()
-                .context("no current song")?
-                .seek(duration)
-                .result_async()
-                .await?;
+                .map_err(|e| eyre!(e))?;
+                return Ok(Response::Ok);
+            }
+            Search { keyword, num } => {
+                if num > 25 {
+                    bail!(RemoteError::Invalid(
+                        "Number of results must be less than 25."
+                    ));
+                }
+                let results = search_youtube(&*self.sources.extractor, &keyword, num).await?;
+                return Ok(Response::SearchResults(results));
+            }
+            // subscriptions belong to the connection, see `WsServer::dispatch`
+            Subscribe | Unsubscribe => return Ok(Response::Ok),
+            _ => {}
+        }
+        // playback changes are reported through track events instead
+        let message_changes_queue = matches!(
+            message,
+            ClearAll | Skip | Enqueue(_) | Move { .. } | Swap { .. } | Remove(_) | Shuffle
+        );
+        let call = self
+            .songbird
+            .get(guild)
+            .ok_or(RemoteError::Conflict("not in a voice channel"))?;
+        // searching takes a while, so it happens before the call is locked
+        let message = match message {
+            Enqueue(q) => Enqueue(resolve_search(&*self.sources.extractor, q).await?),
+            message => message,
+        };
+        let handler = call.lock().await;
+        match message {
+            ClearAll => handler.queue().stop(),
+            Pause => handler.queue().pause()?,
+            Resume => handler.queue().resume()?,
+            Skip => handler.queue().skip()?,
+            Seek(duration) => {
+                let current = handler.queue().current().ok_or(NOTHING_PLAYING)?;
+                drop(handler);
+                current.seek(duration).result_async().await?;
+                self.events.emit(guild, ServerMessage::Seeked(duration));
+            }
+            Enqueue(q) => {
+                // `enqueue` locks the call again once the song is resolved
+                drop(handler);
+                enqueue(self.sources.clone(), guild, q, &call).await?;
+            }
+            Move { from, to } => handler.queue().modify_queue(|x| move_track(x, from, to))?,
+            Swap { a, b } => handler.queue().modify_queue(|x| swap_tracks(x, a, b))?,
+            Remove(index) => {
+                handler.queue().modify_queue(|x| remove_track(x, index))?;
+            }
+            Shuffle => handler.queue().modify_queue(shuffle_queue),
+            ToggleLoop => {
+                let current = handler.queue().current().ok_or(NOTHING_PLAYING)?;
+                toggle_loop(&current)?;
+            }
+            Leave => {
+                drop(handler);
+                leave_channel(&self.songbird, guild).await?;
+            }
+            QueueSnapshot => return Ok(Response::Queue(snapshot(&handler).await)),
+            Join { .. } | Search { .. } | Subscribe | Unsubscribe => {
+                unreachable!("handled above")
+            }
+        }
+        if message_changes_queue {
+            self.events.emit(guild, ServerMessage::QueueChanged);
+        }
+        Ok(Response::Ok)
+    }
+}
+
+/// Accepts WebSocket connections and runs the [`Request`]s they send
+/// against the bot's voice calls.
+pub struct WsServer {
+    remote: Arc<Remote>,
+}
+
+impl WsServer {
+    pub fn new(remote: Arc<Remote>) -> Self {
+        Self { remote }
+    }
+
+    pub async fn listen(self, addr: SocketAddr) -> color_eyre::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(%addr, "websocket server listening");
+        let server = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve(stream).await {
+                    warn!(%peer, "websocket connection closed: {e}");
+                }
+            });
+        }
+    }
+
+    // the handshake callback's error type is dictated by tungstenite
+    #[allow(clippy::result_large_err)]
+    async fn serve(&self, stream: TcpStream) -> Result<(), Error> {
+        let mut secret = None;
+        let mut ws = tokio_tungstenite::accept_hdr_async(stream, |req: &Handshake, res| {
+            secret = bearer_token(req.headers(), req.uri())
+                .filter(|s| self.remote.tokens.get(s).is_some());
+            if secret.is_some() {
+                Ok(res)
+            } else {
+                let mut res = ErrorResponse::new(Some("missing or invalid token".into()));
+                *res.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(res)
+            }
+        })
+        .await?;
+        let secret = secret.unwrap();
+        let mut events = self.remote.events.subscribe();
+        let mut subscriptions = HashSet::new();
+        loop {
+            let response = tokio::select! {
+                msg = ws.next() => {
+                    let Some(msg) = msg else { break };
+                    let text = match msg? {
+                        Message::Text(text) => text,
+                        Message::Close(_) => break,
+                        _ => continue,
+                    };
+                    match self.dispatch(&secret, &text, &mut subscriptions).await {
+                        Ok(response) => response,
+                        Err(e) => Response::Error(e.to_string()),
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok((guild, message)) if subscriptions.contains(&guild) => {
+                        if self.remote.tokens.get(&secret).is_none() {
+                            // the token was revoked while connected
+                            break;
+                        }
+                        Response::Event {
+                            guild: guild.0,
+                            message,
+                        }
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(n)) => {
+                        warn!("websocket client missed {n} events");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            let response = serde_json::to_string(&response)?;
+            ws.send(Message::text(response)).await?;
         }
+        Ok(())
+    }
+
+    async fn dispatch(
+        &self,
+        secret: &str,
+        text: &str,
+        subscriptions: &mut HashSet<GuildId>,
+    ) -> Result<Response, Error> {
+        let token = self
+            .remote
+            .tokens
+            .get(secret)
+            .context("token has been revoked")?;
+        let Request { guild, message } = serde_json::from_str(text)?;
+        let guild = GuildId(guild);
+        match message {
+            ClientMessage::Subscribe => {
+                token.authorize(guild, Scope::Read)?;
+                subscriptions.insert(guild);
+            }
+            ClientMessage::Unsubscribe => {
+                subscriptions.remove(&guild);
+            }
+            _ => {}
+        }
+        self.remote.handle(&token, guild, message).await
     }
-    Ok(())
 }
 
-pub struct WsServer {}
+/// Reads the token from an `Authorization: Bearer` header, or from
+/// a `token` query parameter for clients that can't set headers.
+pub fn bearer_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    if let Some(header) = headers.get("authorization") {
+        let header = header.to_str().ok()?;
+        return header.strip_prefix("Bearer ").map(str::to_owned);
+    }
+    uri.query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(str::to_owned)
+}